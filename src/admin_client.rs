@@ -0,0 +1,301 @@
+use crate::{
+    admin_packet::*, admin_server_packet::*, client_info::*, game_date::GameDate, tcp::read_frame,
+};
+use anyhow::{bail, format_err};
+use std::{collections::VecDeque, ffi::CString};
+use tokio::{
+    io::AsyncWriteExt,
+    net::{TcpStream, ToSocketAddrs},
+};
+
+/// Update pushed by the server to a logged in admin
+#[derive(Clone, Debug, PartialEq)]
+pub enum AdminEvent {
+    /// Days since year 0
+    Date(GameDate),
+    ClientJoin(ClientID),
+    ClientInfo(AdminServerClientInfoPacket),
+    ClientUpdate(AdminServerClientUpdatePacket),
+    ClientQuit(ClientID),
+    ClientError(AdminServerClientErrorPacket),
+    CompanyNew(CompanyID),
+    CompanyInfo(AdminServerCompanyInfoPacket),
+    CompanyUpdate(AdminServerCompanyUpdatePacket),
+    CompanyRemove(AdminServerCompanyRemovePacket),
+    CompanyEconomy(AdminServerCompanyEconomyPacket),
+    CompanyStats(AdminServerCompanyStatsPacket),
+    Chat(AdminServerChatPacket),
+    Console(AdminServerConsolePacket),
+    Gamescript(AdminGamescriptPacket),
+    CmdNames(AdminServerCmdNamesPacket),
+    CmdLogging(AdminServerCmdLoggingPacket),
+    /// A new game was loaded; also updates [`AdminClient::welcome`]
+    Welcome(AdminServerWelcomePacket),
+    Newgame,
+    Shutdown,
+    /// Any packet without a dedicated event, e.g. rcon output not requested
+    /// through [`AdminClient::rcon`]
+    Other(AdminServerPacket),
+}
+
+impl From<AdminServerPacket> for AdminEvent {
+    fn from(packet: AdminServerPacket) -> Self {
+        match packet {
+            AdminServerPacket::Date(data) => Self::Date(data.date),
+            AdminServerPacket::ClientJoin(data) => Self::ClientJoin(data.client_id),
+            AdminServerPacket::ClientInfo(data) => Self::ClientInfo(data),
+            AdminServerPacket::ClientUpdate(data) => Self::ClientUpdate(data),
+            AdminServerPacket::ClientQuit(data) => Self::ClientQuit(data.client_id),
+            AdminServerPacket::ClientError(data) => Self::ClientError(data),
+            AdminServerPacket::CompanyNew(data) => Self::CompanyNew(data.company),
+            AdminServerPacket::CompanyInfo(data) => Self::CompanyInfo(data),
+            AdminServerPacket::CompanyUpdate(data) => Self::CompanyUpdate(data),
+            AdminServerPacket::CompanyRemove(data) => Self::CompanyRemove(data),
+            AdminServerPacket::CompanyEconomy(data) => Self::CompanyEconomy(data),
+            AdminServerPacket::CompanyStats(data) => Self::CompanyStats(data),
+            AdminServerPacket::Chat(data) => Self::Chat(data),
+            AdminServerPacket::Console(data) => Self::Console(data),
+            AdminServerPacket::Gamescript(data) => Self::Gamescript(data),
+            AdminServerPacket::CmdNames(data) => Self::CmdNames(data),
+            AdminServerPacket::CmdLogging(data) => Self::CmdLogging(data),
+            AdminServerPacket::Welcome(data) => Self::Welcome(data),
+            AdminServerPacket::Newgame => Self::Newgame,
+            AdminServerPacket::Shutdown => Self::Shutdown,
+            packet => Self::Other(packet),
+        }
+    }
+}
+
+/// Logged in connection to the admin port of a server
+#[derive(Debug)]
+pub struct AdminClient {
+    stream: TcpStream,
+    protocol: AdminServerProtocolPacket,
+    welcome: AdminServerWelcomePacket,
+    /// Events received while waiting for the answer to a request
+    events: VecDeque<AdminEvent>,
+}
+
+impl AdminClient {
+    /// Connect and log in with `ADMIN_JOIN`
+    pub async fn connect<A: ToSocketAddrs>(
+        addr: A,
+        admin_name: &str,
+        password: &str,
+    ) -> anyhow::Result<Self> {
+        let mut stream = TcpStream::connect(addr).await?;
+        let join = AdminPacket::Join(AdminJoinPacket {
+            password: CString::new(password)?,
+            admin_name: CString::new(admin_name)?,
+            admin_version: CString::new(env!("CARGO_PKG_VERSION"))?,
+        });
+        stream.write_all(&join.to_bytes()?).await?;
+
+        let mut protocol = None;
+        loop {
+            match Self::read_packet(&mut stream).await? {
+                AdminServerPacket::Protocol(data) => protocol = Some(data),
+                AdminServerPacket::Welcome(welcome) => {
+                    let protocol =
+                        protocol.ok_or_else(|| format_err!("welcome before protocol"))?;
+                    return Ok(Self {
+                        stream,
+                        protocol,
+                        welcome,
+                        events: VecDeque::new(),
+                    });
+                }
+                AdminServerPacket::Error(data) => bail!("server error: {}", data.error),
+                AdminServerPacket::Full => bail!("server is full"),
+                AdminServerPacket::Banned => bail!("banned from server"),
+                packet => bail!("unexpected packet while logging in: {:?}", packet),
+            }
+        }
+    }
+
+    /// Update types and frequencies supported by the server
+    pub fn protocol(&self) -> &AdminServerProtocolPacket {
+        &self.protocol
+    }
+
+    /// Game information sent on login and after every new game
+    pub fn welcome(&self) -> &AdminServerWelcomePacket {
+        &self.welcome
+    }
+
+    pub async fn send(&mut self, packet: &AdminPacket) -> anyhow::Result<()> {
+        self.stream.write_all(&packet.to_bytes()?).await?;
+
+        Ok(())
+    }
+
+    /// Ask the server to push updates of `update_type` at `frequency`
+    ///
+    /// Fails without sending anything if the server did not advertise the
+    /// frequency in `SERVER_PROTOCOL`, as it would close the connection.
+    pub async fn subscribe(
+        &mut self,
+        update_type: AdminUpdateType,
+        frequency: AdminUpdateFrequency,
+    ) -> anyhow::Result<()> {
+        if !self.protocol.supports(update_type, frequency) {
+            bail!(
+                "server does not support {:?} updates at {:?}",
+                update_type,
+                frequency
+            );
+        }
+
+        self.send(&AdminPacket::UpdateFrequency(AdminUpdateFrequencyPacket {
+            update_type,
+            frequency,
+        }))
+        .await
+    }
+
+    /// Request a single update; the answer arrives as an event
+    pub async fn poll(&mut self, update_type: AdminUpdateType, data: u32) -> anyhow::Result<()> {
+        if !self
+            .protocol
+            .supports(update_type, AdminUpdateFrequency::POLL)
+        {
+            bail!("server does not support polling {:?} updates", update_type);
+        }
+
+        self.send(&AdminPacket::Poll(AdminPollPacket { update_type, data }))
+            .await
+    }
+
+    /// Wait for the next update from the server
+    pub async fn next_event(&mut self) -> anyhow::Result<AdminEvent> {
+        if let Some(event) = self.events.pop_front() {
+            return Ok(event);
+        }
+
+        self.read_event().await
+    }
+
+    /// Run a console command and collect its output until `SERVER_RCON_END`
+    pub async fn rcon(&mut self, command: &str) -> anyhow::Result<Vec<String>> {
+        self.send(&AdminPacket::Rcon(AdminRconPacket {
+            command: CString::new(command)?,
+        }))
+        .await?;
+
+        let mut lines = vec![];
+        loop {
+            match self.read_event().await? {
+                AdminEvent::Other(AdminServerPacket::Rcon(data)) => {
+                    lines.push(data.output.to_string_lossy().into_owned())
+                }
+                AdminEvent::Other(AdminServerPacket::RconEnd(_)) => return Ok(lines),
+                event => self.events.push_back(event),
+            }
+        }
+    }
+
+    /// Send a message to the running GameScript
+    #[cfg(feature = "serde")]
+    pub async fn send_gamescript<T: serde::Serialize>(&mut self, value: &T) -> anyhow::Result<()> {
+        self.send(&AdminPacket::Gamescript(AdminGamescriptPacket::encode(
+            value,
+        )?))
+        .await
+    }
+
+    /// Log out with `ADMIN_QUIT`
+    pub async fn quit(mut self) -> anyhow::Result<()> {
+        self.send(&AdminPacket::Quit).await?;
+        self.stream.shutdown().await?;
+
+        Ok(())
+    }
+
+    async fn read_event(&mut self) -> anyhow::Result<AdminEvent> {
+        match Self::read_packet(&mut self.stream).await? {
+            AdminServerPacket::Error(data) => bail!("server error: {}", data.error),
+            AdminServerPacket::Welcome(welcome) => {
+                self.welcome = welcome.clone();
+                Ok(AdminEvent::Welcome(welcome))
+            }
+            packet => Ok(packet.into()),
+        }
+    }
+
+    async fn read_packet(stream: &mut TcpStream) -> anyhow::Result<AdminServerPacket> {
+        let frame = read_frame(stream).await?;
+        let (_, packet) = AdminServerPacket::from_bytes(&frame)
+            .map_err(|e| format_err!("malformed packet: {:?}", e))?;
+
+        Ok(packet)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rcon::ServerRconPacket;
+    use maplit::btreemap;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_admin_client_rcon() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let welcome = AdminServerWelcomePacket {
+            server_name: CString::new("Test").unwrap(),
+            network_revision: CString::new("13.4").unwrap(),
+            dedicated: true,
+            map_name: CString::new("Random Map").unwrap(),
+            generation_seed: 1,
+            landscape: 0,
+            start_date: GameDate(693961),
+            map_width: 256,
+            map_height: 256,
+        };
+        let responses = [
+            AdminServerPacket::Protocol(AdminServerProtocolPacket {
+                version: 3,
+                update_frequencies: btreemap! {
+                    AdminUpdateType::Date => AdminUpdateFrequency::all(),
+                },
+            }),
+            AdminServerPacket::Welcome(welcome.clone()),
+            AdminServerPacket::Rcon(ServerRconPacket {
+                colour: 1,
+                output: CString::new("Current date: 1950-01-01").unwrap(),
+            }),
+            AdminServerPacket::ClientJoin(AdminServerClientIdPacket {
+                client_id: ClientID(2),
+            }),
+            AdminServerPacket::RconEnd(AdminServerRconEndPacket {
+                command: CString::new("getdate").unwrap(),
+            }),
+        ];
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let frame = read_frame(&mut stream).await.unwrap();
+            let (_, join) = AdminPacket::from_bytes(&frame).unwrap();
+            assert!(matches!(join, AdminPacket::Join(_)));
+            for packet in &responses[..2] {
+                stream.write_all(&packet.to_bytes().unwrap()).await.unwrap();
+            }
+            let frame = read_frame(&mut stream).await.unwrap();
+            let (_, rcon) = AdminPacket::from_bytes(&frame).unwrap();
+            assert!(matches!(rcon, AdminPacket::Rcon(_)));
+            for packet in &responses[2..] {
+                stream.write_all(&packet.to_bytes().unwrap()).await.unwrap();
+            }
+        });
+
+        let mut client = AdminClient::connect(addr, "test", "secret").await.unwrap();
+        assert_eq!(&welcome, client.welcome());
+
+        let output = client.rcon("getdate").await.unwrap();
+        assert_eq!(vec!["Current date: 1950-01-01".to_string()], output);
+        assert_eq!(
+            AdminEvent::ClientJoin(ClientID(2)),
+            client.next_event().await.unwrap()
+        );
+    }
+}
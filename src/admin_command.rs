@@ -0,0 +1,236 @@
+use crate::{admin_server_packet::*, client_info::*, util::*};
+use nom::{
+    self,
+    combinator::{all_consuming, map},
+    number::complete::*,
+    sequence::tuple,
+    *,
+};
+use std::{collections::BTreeMap, ffi::CString};
+
+/// Decoded parameters of a logged command
+#[derive(Clone, Debug, PartialEq)]
+pub enum CommandParams {
+    LandscapeClear {
+        tile: u32,
+    },
+    ClearArea {
+        tile: u32,
+        start_tile: u32,
+        diagonal: bool,
+    },
+    TerraformLand {
+        tile: u32,
+        slope: u8,
+        dir_up: bool,
+    },
+    LevelLand {
+        tile: u32,
+        start_tile: u32,
+        diagonal: bool,
+        mode: u8,
+    },
+    SellVehicle {
+        vehicle: u32,
+        sell_chain: bool,
+        backup_order: bool,
+        client_id: ClientID,
+    },
+    GiveMoney {
+        money: i64,
+        dest_company: CompanyID,
+    },
+    RenameCompany {
+        name: CString,
+    },
+    RenamePresident {
+        name: CString,
+    },
+    Pause {
+        mode: u8,
+        pause: bool,
+    },
+    /// Parameters of a command without a decoder, or that did not match its signature
+    Raw(Vec<u8>),
+}
+
+impl CommandParams {
+    /// Decode the parameters of the command called `name` in `CMD_NAMES`
+    ///
+    /// Commands are matched by name as their IDs change between releases.
+    pub fn decode(name: &str, data: &[u8]) -> Self {
+        let result = match name {
+            "CmdLandscapeClear" => {
+                all_consuming(map(le_u32, |tile| Self::LandscapeClear { tile })).parse(data)
+            }
+            "CmdClearArea" => all_consuming(map(
+                tuple((le_u32, le_u32, boolean)),
+                |(tile, start_tile, diagonal)| Self::ClearArea {
+                    tile,
+                    start_tile,
+                    diagonal,
+                },
+            ))
+            .parse(data),
+            "CmdTerraformLand" => all_consuming(map(
+                tuple((le_u32, le_u8, boolean)),
+                |(tile, slope, dir_up)| Self::TerraformLand {
+                    tile,
+                    slope,
+                    dir_up,
+                },
+            ))
+            .parse(data),
+            "CmdLevelLand" => all_consuming(map(
+                tuple((le_u32, le_u32, boolean, le_u8)),
+                |(tile, start_tile, diagonal, mode)| Self::LevelLand {
+                    tile,
+                    start_tile,
+                    diagonal,
+                    mode,
+                },
+            ))
+            .parse(data),
+            "CmdSellVehicle" => all_consuming(map(
+                tuple((le_u32, boolean, boolean, client_id)),
+                |(vehicle, sell_chain, backup_order, client_id)| Self::SellVehicle {
+                    vehicle,
+                    sell_chain,
+                    backup_order,
+                    client_id,
+                },
+            ))
+            .parse(data),
+            "CmdGiveMoney" => {
+                all_consuming(map(tuple((le_i64, company_id)), |(money, dest_company)| {
+                    Self::GiveMoney {
+                        money,
+                        dest_company,
+                    }
+                }))
+                .parse(data)
+            }
+            "CmdRenameCompany" => {
+                all_consuming(map(read_cstring, |name| Self::RenameCompany { name })).parse(data)
+            }
+            "CmdRenamePresident" => {
+                all_consuming(map(read_cstring, |name| Self::RenamePresident { name })).parse(data)
+            }
+            "CmdPause" => all_consuming(map(tuple((le_u8, boolean)), |(mode, pause)| {
+                Self::Pause { mode, pause }
+            }))
+            .parse(data),
+            _ => return Self::Raw(data.to_vec()),
+        };
+
+        result.map_or_else(|_| Self::Raw(data.to_vec()), |(_, params)| params)
+    }
+}
+
+/// Readable entry of the server's command log
+#[derive(Clone, Debug, PartialEq)]
+pub struct CommandRecord {
+    pub client_id: ClientID,
+    pub company: CompanyID,
+    pub command: u16,
+    /// `None` if the ID was not in any `SERVER_CMD_NAMES` seen so far
+    pub name: Option<String>,
+    pub frame: u32,
+    pub params: CommandParams,
+}
+
+/// Turns `SERVER_CMD_LOGGING` packets into [`CommandRecord`]s
+///
+/// Feed it every `SERVER_CMD_NAMES` packet first; the server splits the name
+/// table over several of them.
+#[derive(Clone, Debug, Default)]
+pub struct CommandLogDecoder {
+    names: BTreeMap<u16, String>,
+}
+
+impl CommandLogDecoder {
+    pub fn add_names(&mut self, packet: &AdminServerCmdNamesPacket) {
+        self.names.extend(
+            packet
+                .names
+                .iter()
+                .map(|(&id, name)| (id, name.to_string_lossy().into_owned())),
+        );
+    }
+
+    pub fn name(&self, command: u16) -> Option<&str> {
+        self.names.get(&command).map(String::as_str)
+    }
+
+    pub fn decode(&self, packet: &AdminServerCmdLoggingPacket) -> CommandRecord {
+        let name = self.name(packet.command);
+        CommandRecord {
+            client_id: packet.client_id,
+            company: packet.company,
+            command: packet.command,
+            name: name.map(str::to_string),
+            frame: packet.frame,
+            params: name.map_or_else(
+                || CommandParams::Raw(packet.data.clone()),
+                |name| CommandParams::decode(name, &packet.data),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex_literal::hex;
+    use maplit::btreemap;
+
+    #[test]
+    fn test_decode_command_log() {
+        let mut decoder = CommandLogDecoder::default();
+        decoder.add_names(&AdminServerCmdNamesPacket {
+            names: btreemap! {
+                0 => CString::new("CmdBuildRailroadTrack").unwrap(),
+                44 => CString::new("CmdGiveMoney").unwrap(),
+            },
+        });
+        decoder.add_names(&AdminServerCmdNamesPacket {
+            names: btreemap! { 57 => CString::new("CmdRenameCompany").unwrap() },
+        });
+        let packet = |command, data: &[u8]| AdminServerCmdLoggingPacket {
+            client_id: ClientID(3),
+            company: CompanyID(0),
+            command,
+            data: data.to_vec(),
+            frame: 1000,
+        };
+
+        let record = decoder.decode(&packet(44, &hex!("e803000000000000 02")));
+        assert_eq!(Some("CmdGiveMoney"), record.name.as_deref());
+        assert_eq!(
+            CommandParams::GiveMoney {
+                money: 1000,
+                dest_company: CompanyID(2),
+            },
+            record.params
+        );
+
+        let record = decoder.decode(&packet(57, &hex!("4163656d00")));
+        assert_eq!(
+            CommandParams::RenameCompany {
+                name: CString::new("Acem").unwrap(),
+            },
+            record.params
+        );
+
+        // Trailing bytes mean the signature differs from what we expect
+        let record = decoder.decode(&packet(44, &hex!("e803000000000000 02 00")));
+        assert_eq!(
+            CommandParams::Raw(hex!("e803000000000000 02 00").to_vec()),
+            record.params
+        );
+
+        let record = decoder.decode(&packet(99, &hex!("01")));
+        assert_eq!(None, record.name);
+        assert_eq!(CommandParams::Raw(vec![1]), record.params);
+    }
+}
@@ -0,0 +1,317 @@
+use crate::{
+    admin_packet::*, admin_server_packet::*, game_date::GameDate, network_error::NetworkErrorCode,
+    rcon::ServerRconPacket, tcp::read_frame,
+};
+use anyhow::{bail, format_err};
+use maplit::btreemap;
+use std::{collections::HashMap, ffi::CString, net::SocketAddr};
+use tokio::{
+    io::AsyncWriteExt,
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+};
+
+/// `TextColour` used by the server for console errors
+const CC_ERROR: u16 = 3;
+
+/// Scriptable admin port server for testing admin tools without a game server
+///
+/// Accepts a single admin, answers RCON with canned output and delivers the
+/// configured events once the admin subscribes to or polls their update type.
+/// Packets the real server would reject close the connection with `SERVER_ERROR`
+/// and make [`serve`](Self::serve) fail.
+#[derive(Debug)]
+pub struct MockAdminServer {
+    listener: TcpListener,
+    password: CString,
+    protocol: AdminServerProtocolPacket,
+    welcome: AdminServerWelcomePacket,
+    rcon: HashMap<String, Vec<String>>,
+    events: Vec<AdminServerPacket>,
+}
+
+impl MockAdminServer {
+    pub async fn bind<A: ToSocketAddrs>(addr: A) -> std::io::Result<Self> {
+        use AdminUpdateFrequency as F;
+        use AdminUpdateType as T;
+        let periodic = F::WEEKLY | F::MONTHLY | F::QUARTERLY | F::ANNUALLY;
+
+        Ok(Self {
+            listener: TcpListener::bind(addr).await?,
+            password: CString::default(),
+            protocol: AdminServerProtocolPacket {
+                version: 3,
+                update_frequencies: btreemap! {
+                    T::Date => F::POLL | F::DAILY | periodic,
+                    T::ClientInfo => F::POLL | F::AUTOMATIC,
+                    T::CompanyInfo => F::POLL | F::AUTOMATIC,
+                    T::CompanyEconomy => F::POLL | periodic,
+                    T::CompanyStats => F::POLL | periodic,
+                    T::Chat => F::AUTOMATIC,
+                    T::Console => F::AUTOMATIC,
+                    T::CmdNames => F::POLL,
+                    T::CmdLogging => F::AUTOMATIC,
+                    T::Gamescript => F::AUTOMATIC,
+                },
+            },
+            welcome: AdminServerWelcomePacket {
+                server_name: CString::new("Mock server").unwrap(),
+                network_revision: CString::new("13.4").unwrap(),
+                dedicated: true,
+                map_name: CString::new("Random Map").unwrap(),
+                generation_seed: 0,
+                landscape: 0,
+                start_date: GameDate(693961),
+                map_width: 256,
+                map_height: 256,
+            },
+            rcon: HashMap::new(),
+            events: vec![],
+        })
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Admin password the client has to log in with; empty by default
+    pub fn password(mut self, password: &str) -> anyhow::Result<Self> {
+        self.password =
+            CString::new(password).map_err(|_| format_err!("password contains a NUL byte"))?;
+        Ok(self)
+    }
+
+    /// Replace the advertised update types and frequencies
+    pub fn protocol(mut self, protocol: AdminServerProtocolPacket) -> Self {
+        self.protocol = protocol;
+        self
+    }
+
+    pub fn welcome(mut self, welcome: AdminServerWelcomePacket) -> Self {
+        self.welcome = welcome;
+        self
+    }
+
+    /// Canned output of a console command
+    pub fn rcon<I, S>(mut self, command: &str, output: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.rcon.insert(
+            command.to_string(),
+            output.into_iter().map(Into::into).collect(),
+        );
+        self
+    }
+
+    /// Packet sent once the admin asks for its update type, or right after
+    /// login if it has none
+    pub fn event(mut self, packet: AdminServerPacket) -> Self {
+        self.events.push(packet);
+        self
+    }
+
+    /// Serve one admin connection until it quits or disconnects
+    ///
+    /// Returns every packet the admin sent.
+    pub async fn serve(self) -> anyhow::Result<Vec<AdminPacket>> {
+        let (stream, _) = self.listener.accept().await?;
+        let mut conn = MockAdminConnection {
+            stream,
+            received: vec![],
+        };
+
+        match conn.read_packet().await? {
+            Some(AdminPacket::Join(join)) if join.password == self.password => {}
+            Some(AdminPacket::Join(_)) => {
+                conn.error(NetworkErrorCode::WrongPassword).await?;
+                bail!("admin sent wrong password");
+            }
+            packet => {
+                conn.error(NetworkErrorCode::NotExpected).await?;
+                bail!("expected ADMIN_JOIN, got {:?}", packet);
+            }
+        }
+        conn.write(&AdminServerPacket::Protocol(self.protocol.clone()))
+            .await?;
+        conn.write(&AdminServerPacket::Welcome(self.welcome.clone()))
+            .await?;
+
+        let (mut pending, unconditional): (Vec<_>, Vec<_>) = self
+            .events
+            .into_iter()
+            .partition(|packet| packet.update_type().is_some());
+        for packet in &unconditional {
+            conn.write(packet).await?;
+        }
+
+        while let Some(packet) = conn.read_packet().await? {
+            let requested = match &packet {
+                AdminPacket::Join(_) => {
+                    conn.error(NetworkErrorCode::NotExpected).await?;
+                    bail!("admin sent ADMIN_JOIN twice");
+                }
+                AdminPacket::Quit => break,
+                AdminPacket::UpdateFrequency(data) => (data.update_type, data.frequency),
+                AdminPacket::Poll(data) => (data.update_type, AdminUpdateFrequency::POLL),
+                AdminPacket::Rcon(data) => {
+                    let command = data.command.to_string_lossy();
+                    let (colour, output) = match self.rcon.get(command.as_ref()) {
+                        Some(output) => (1, output.clone()),
+                        None => (
+                            CC_ERROR,
+                            vec![format!("ERROR: command not found: '{}'", command)],
+                        ),
+                    };
+                    for line in output {
+                        conn.write(&AdminServerPacket::Rcon(ServerRconPacket {
+                            colour,
+                            output: CString::new(line)?,
+                        }))
+                        .await?;
+                    }
+                    conn.write(&AdminServerPacket::RconEnd(AdminServerRconEndPacket {
+                        command: data.command.clone(),
+                    }))
+                    .await?;
+                    continue;
+                }
+                AdminPacket::Ping(data) => {
+                    conn.write(&AdminServerPacket::Pong(AdminServerPongPacket {
+                        payload: data.payload,
+                    }))
+                    .await?;
+                    continue;
+                }
+                AdminPacket::Chat(_)
+                | AdminPacket::Gamescript(_)
+                | AdminPacket::ExternalChat(_) => continue,
+            };
+
+            let (update_type, frequency) = requested;
+            if !self.protocol.supports(update_type, frequency) {
+                conn.error(NetworkErrorCode::IllegalPacket).await?;
+                bail!(
+                    "admin requested unsupported {:?} updates at {:?}",
+                    update_type,
+                    frequency
+                );
+            }
+
+            let (due, rest): (Vec<_>, Vec<_>) = pending
+                .into_iter()
+                .partition(|packet| packet.update_type() == Some(update_type));
+            pending = rest;
+            for packet in &due {
+                conn.write(packet).await?;
+            }
+        }
+
+        Ok(conn.received)
+    }
+}
+
+#[derive(Debug)]
+struct MockAdminConnection {
+    stream: TcpStream,
+    received: Vec<AdminPacket>,
+}
+
+impl MockAdminConnection {
+    /// Next packet from the admin, `None` once it disconnected
+    async fn read_packet(&mut self) -> anyhow::Result<Option<AdminPacket>> {
+        let frame = match read_frame(&mut self.stream).await {
+            Ok(frame) => frame,
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let (_, packet) = AdminPacket::from_bytes(&frame)
+            .map_err(|e| format_err!("malformed packet: {:?}", e))?;
+        self.received.push(packet.clone());
+
+        Ok(Some(packet))
+    }
+
+    async fn write(&mut self, packet: &AdminServerPacket) -> anyhow::Result<()> {
+        self.stream.write_all(&packet.to_bytes()?).await?;
+
+        Ok(())
+    }
+
+    async fn error(&mut self, error: NetworkErrorCode) -> anyhow::Result<()> {
+        self.write(&AdminServerPacket::Error(AdminServerErrorPacket { error }))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{admin_client::*, client_info::*};
+
+    #[tokio::test]
+    async fn test_mock_admin_server() {
+        let server = MockAdminServer::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .password("secret")
+            .unwrap()
+            .rcon("getdate", ["Date: 1950-01-01"])
+            .event(AdminServerPacket::ClientJoin(AdminServerClientIdPacket {
+                client_id: ClientID(2),
+            }))
+            .event(AdminServerPacket::Date(AdminServerDatePacket {
+                date: GameDate(712223),
+            }));
+        let addr = server.local_addr().unwrap();
+        let server = tokio::spawn(server.serve());
+
+        let mut client = AdminClient::connect(addr, "test", "secret").await.unwrap();
+        assert_eq!(
+            vec!["Date: 1950-01-01".to_string()],
+            client.rcon("getdate").await.unwrap()
+        );
+        assert!(client
+            .subscribe(AdminUpdateType::Chat, AdminUpdateFrequency::DAILY)
+            .await
+            .is_err());
+        client
+            .subscribe(AdminUpdateType::ClientInfo, AdminUpdateFrequency::AUTOMATIC)
+            .await
+            .unwrap();
+        assert_eq!(
+            AdminEvent::ClientJoin(ClientID(2)),
+            client.next_event().await.unwrap()
+        );
+        client.poll(AdminUpdateType::Date, 0).await.unwrap();
+        assert_eq!(
+            AdminEvent::Date(GameDate(712223)),
+            client.next_event().await.unwrap()
+        );
+        client.quit().await.unwrap();
+
+        let received = server.await.unwrap().unwrap();
+        assert_eq!(5, received.len());
+        assert_eq!(AdminPacket::Quit, received[4]);
+    }
+
+    #[tokio::test]
+    async fn test_mock_admin_server_wrong_password() {
+        let server = MockAdminServer::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .password("secret")
+            .unwrap();
+        let addr = server.local_addr().unwrap();
+        let server = tokio::spawn(server.serve());
+
+        assert!(AdminClient::connect(addr, "test", "wrong").await.is_err());
+        assert!(server.await.unwrap().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_mock_admin_server_invalid_password() {
+        let server = MockAdminServer::bind("127.0.0.1:0").await.unwrap();
+        assert!(server.password("sec\0ret").is_err());
+    }
+}
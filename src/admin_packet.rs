@@ -0,0 +1,519 @@
+use crate::{chat::*, util::*};
+use anyhow::bail;
+use bitflags::bitflags;
+use byteorder::{LittleEndian, WriteBytesExt};
+use nom::{
+    self,
+    bytes::complete::take,
+    combinator::{map, map_opt},
+    number::complete::*,
+    sequence::tuple,
+    *,
+};
+use num_enum::{FromPrimitive, IntoPrimitive, TryFromPrimitive};
+// Takes precedence over `nom::Into` from the glob import
+use std::{convert::Into, ffi::CString};
+use strum::EnumDiscriminants;
+
+/// Default TCP port of the admin interface
+pub const ADMIN_PORT: u16 = 3977;
+
+/// Packet sent by an admin to the server over the admin port
+#[derive(Clone, Debug, PartialEq, EnumDiscriminants)]
+#[strum_discriminants(repr(u8))]
+#[strum_discriminants(derive(IntoPrimitive, TryFromPrimitive))]
+pub enum AdminPacket {
+    Join(AdminJoinPacket),
+    Quit,
+    UpdateFrequency(AdminUpdateFrequencyPacket),
+    Poll(AdminPollPacket),
+    Chat(AdminChatPacket),
+    Rcon(AdminRconPacket),
+    Gamescript(AdminGamescriptPacket),
+    Ping(AdminPingPacket),
+    ExternalChat(AdminExternalChatPacket),
+}
+
+impl AdminPacket {
+    /// Parse a single size-prefixed admin packet
+    pub fn from_bytes(input: &[u8]) -> IResult<&[u8], AdminPacket> {
+        let (input, size) = le_u16(input)?;
+        let (input, body) = take(usize::from(size).saturating_sub(2)).parse(input)?;
+        let (payload, packet_type) =
+            map_opt(le_u8, |v| AdminPacketDiscriminants::try_from(v).ok()).parse(body)?;
+
+        use AdminPacketDiscriminants as D;
+        let packet = match packet_type {
+            D::Join => {
+                map(AdminJoinPacket::from_bytes, Self::Join)
+                    .parse(payload)?
+                    .1
+            }
+            D::Quit => Self::Quit,
+            D::UpdateFrequency => {
+                map(
+                    AdminUpdateFrequencyPacket::from_bytes,
+                    Self::UpdateFrequency,
+                )
+                .parse(payload)?
+                .1
+            }
+            D::Poll => {
+                map(AdminPollPacket::from_bytes, Self::Poll)
+                    .parse(payload)?
+                    .1
+            }
+            D::Chat => {
+                map(AdminChatPacket::from_bytes, Self::Chat)
+                    .parse(payload)?
+                    .1
+            }
+            D::Rcon => {
+                map(AdminRconPacket::from_bytes, Self::Rcon)
+                    .parse(payload)?
+                    .1
+            }
+            D::Gamescript => {
+                map(AdminGamescriptPacket::from_bytes, Self::Gamescript)
+                    .parse(payload)?
+                    .1
+            }
+            D::Ping => {
+                map(AdminPingPacket::from_bytes, Self::Ping)
+                    .parse(payload)?
+                    .1
+            }
+            D::ExternalChat => {
+                map(AdminExternalChatPacket::from_bytes, Self::ExternalChat)
+                    .parse(payload)?
+                    .1
+            }
+        };
+
+        Ok((input, packet))
+    }
+
+    pub fn to_bytes(&self) -> std::io::Result<Vec<u8>> {
+        let buf = &mut vec![];
+        buf.push(AdminPacketDiscriminants::from(self).into());
+
+        match self {
+            AdminPacket::Join(data) => data.write_pkt(buf)?,
+            AdminPacket::Quit => {}
+            AdminPacket::UpdateFrequency(data) => data.write_pkt(buf)?,
+            AdminPacket::Poll(data) => data.write_pkt(buf)?,
+            AdminPacket::Chat(data) => data.write_pkt(buf)?,
+            AdminPacket::Rcon(data) => data.write_pkt(buf)?,
+            AdminPacket::Gamescript(data) => data.write_pkt(buf)?,
+            AdminPacket::Ping(data) => data.write_pkt(buf)?,
+            AdminPacket::ExternalChat(data) => data.write_pkt(buf)?,
+        }
+
+        let mut out = vec![];
+        out.write_u16::<LittleEndian>(buf.len() as u16 + 2)?;
+        out.append(buf);
+
+        Ok(out)
+    }
+}
+
+/// `ADMIN_JOIN`: log in with the admin password
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdminJoinPacket {
+    pub password: CString,
+    pub admin_name: CString,
+    pub admin_version: CString,
+}
+
+impl ByteWriter for AdminJoinPacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.extend_from_slice(self.password.to_bytes_with_nul());
+        buf.extend_from_slice(self.admin_name.to_bytes_with_nul());
+        buf.extend_from_slice(self.admin_version.to_bytes_with_nul());
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for AdminJoinPacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(
+            tuple((read_cstring, read_cstring, read_cstring)),
+            |(password, admin_name, admin_version)| Self {
+                password,
+                admin_name,
+                admin_version,
+            },
+        )
+        .parse(input)
+    }
+}
+
+/// Kind of update an admin can subscribe to or poll
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, IntoPrimitive, FromPrimitive,
+)]
+#[repr(u16)]
+pub enum AdminUpdateType {
+    Date,
+    ClientInfo,
+    CompanyInfo,
+    CompanyEconomy,
+    CompanyStats,
+    Chat,
+    Console,
+    CmdNames,
+    CmdLogging,
+    Gamescript,
+    /// Type added by a newer OpenTTD
+    #[num_enum(catch_all)]
+    Unknown(u16),
+}
+
+pub fn admin_update_type(input: &[u8]) -> IResult<&[u8], AdminUpdateType> {
+    map(le_u16, AdminUpdateType::from).parse(input)
+}
+
+bitflags! {
+    /// How often the server sends an update type
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    pub struct AdminUpdateFrequency: u16 {
+        /// Only when requested with `ADMIN_POLL`
+        const POLL = 0x01;
+        const DAILY = 0x02;
+        const WEEKLY = 0x04;
+        const MONTHLY = 0x08;
+        const QUARTERLY = 0x10;
+        const ANNUALLY = 0x20;
+        /// Whenever the underlying data changes
+        const AUTOMATIC = 0x40;
+    }
+}
+
+pub fn admin_update_frequency(input: &[u8]) -> IResult<&[u8], AdminUpdateFrequency> {
+    map(le_u16, AdminUpdateFrequency::from_bits_retain).parse(input)
+}
+
+/// `ADMIN_UPDATE_FREQUENCY`: subscribe to an update type
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdminUpdateFrequencyPacket {
+    pub update_type: AdminUpdateType,
+    pub frequency: AdminUpdateFrequency,
+}
+
+impl ByteWriter for AdminUpdateFrequencyPacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.write_u16::<LittleEndian>(self.update_type.into())?;
+        buf.write_u16::<LittleEndian>(self.frequency.bits())?;
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for AdminUpdateFrequencyPacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(
+            tuple((admin_update_type, admin_update_frequency)),
+            |(update_type, frequency)| Self {
+                update_type,
+                frequency,
+            },
+        )
+        .parse(input)
+    }
+}
+
+/// `ADMIN_POLL`: request an update right away
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdminPollPacket {
+    pub update_type: AdminUpdateType,
+    /// Client or company to poll, `u32::MAX` for all of them
+    pub data: u32,
+}
+
+impl ByteWriter for AdminPollPacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.write_u8(u16::from(self.update_type) as u8)?;
+        buf.write_u32::<LittleEndian>(self.data)?;
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for AdminPollPacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(
+            tuple((map(le_u8, |v| AdminUpdateType::from(u16::from(v))), le_u32)),
+            |(update_type, data)| Self { update_type, data },
+        )
+        .parse(input)
+    }
+}
+
+/// `ADMIN_CHAT`: send a chat message in the name of the server
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdminChatPacket {
+    pub action: NetworkAction,
+    pub dest_type: DestType,
+    /// Client or company ID depending on `dest_type`
+    pub dest: u32,
+    pub message: CString,
+}
+
+impl ByteWriter for AdminChatPacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.write_u8(self.action.into())?;
+        buf.write_u8(self.dest_type.into())?;
+        buf.write_u32::<LittleEndian>(self.dest)?;
+        buf.extend_from_slice(self.message.to_bytes_with_nul());
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for AdminChatPacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(
+            tuple((network_action, dest_type, le_u32, read_cstring)),
+            |(action, dest_type, dest, message)| Self {
+                action,
+                dest_type,
+                dest,
+                message,
+            },
+        )
+        .parse(input)
+    }
+}
+
+/// `ADMIN_EXTERNAL_CHAT`: relay a message from another chat service
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdminExternalChatPacket {
+    /// Name of the service, e.g. "IRC"
+    pub source: CString,
+    /// `TextColour` of the message
+    pub colour: u16,
+    pub user: CString,
+    pub message: CString,
+}
+
+impl ByteWriter for AdminExternalChatPacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.extend_from_slice(self.source.to_bytes_with_nul());
+        buf.write_u16::<LittleEndian>(self.colour)?;
+        buf.extend_from_slice(self.user.to_bytes_with_nul());
+        buf.extend_from_slice(self.message.to_bytes_with_nul());
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for AdminExternalChatPacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(
+            tuple((read_cstring, le_u16, read_cstring, read_cstring)),
+            |(source, colour, user, message)| Self {
+                source,
+                colour,
+                user,
+                message,
+            },
+        )
+        .parse(input)
+    }
+}
+
+/// `ADMIN_RCON`: execute a console command
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdminRconPacket {
+    pub command: CString,
+}
+
+impl ByteWriter for AdminRconPacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.extend_from_slice(self.command.to_bytes_with_nul());
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for AdminRconPacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(read_cstring, |command| Self { command }).parse(input)
+    }
+}
+
+/// Longest GameScript JSON message the server accepts or sends, including the terminator
+pub const GAMESCRIPT_JSON_LENGTH: usize = 9000;
+
+/// `ADMIN_GAMESCRIPT`: JSON message for the running GameScript
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdminGamescriptPacket {
+    pub json: CString,
+}
+
+impl AdminGamescriptPacket {
+    /// Wrap a JSON string, checking that it fits into a single packet
+    pub fn new(json: impl Into<Vec<u8>>) -> anyhow::Result<Self> {
+        let json = CString::new(json)?;
+        let len = json.as_bytes_with_nul().len();
+        if len > GAMESCRIPT_JSON_LENGTH {
+            bail!(
+                "GameScript JSON is {} bytes, at most {} fit into a packet",
+                len,
+                GAMESCRIPT_JSON_LENGTH
+            );
+        }
+
+        Ok(Self { json })
+    }
+
+    /// Serialize a message for the GameScript
+    #[cfg(feature = "serde")]
+    pub fn encode<T: serde::Serialize>(value: &T) -> anyhow::Result<Self> {
+        Self::new(serde_json::to_vec(value)?)
+    }
+
+    /// Deserialize a message from the GameScript, e.g. into a [`serde_json::Value`]
+    #[cfg(feature = "serde")]
+    pub fn decode<T: serde::de::DeserializeOwned>(&self) -> anyhow::Result<T> {
+        Ok(serde_json::from_slice(self.json.as_bytes())?)
+    }
+}
+
+impl ByteWriter for AdminGamescriptPacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.extend_from_slice(self.json.to_bytes_with_nul());
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for AdminGamescriptPacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(read_cstring, |json| Self { json }).parse(input)
+    }
+}
+
+/// `ADMIN_PING`: the server echoes the payload back in `SERVER_PONG`
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdminPingPacket {
+    pub payload: u32,
+}
+
+impl ByteWriter for AdminPingPacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.write_u32::<LittleEndian>(self.payload)?;
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for AdminPingPacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(le_u32, |payload| Self { payload }).parse(input)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex_literal::hex;
+
+    fn fixtures() -> Vec<(Vec<u8>, AdminPacket)> {
+        vec![
+            (
+                hex!("0e00 00 707700 626f7400 312e3000").into(),
+                AdminPacket::Join(AdminJoinPacket {
+                    password: CString::new("pw").unwrap(),
+                    admin_name: CString::new("bot").unwrap(),
+                    admin_version: CString::new("1.0").unwrap(),
+                }),
+            ),
+            (hex!("0300 01").into(), AdminPacket::Quit),
+            (
+                hex!("0700 02 0600 4000").into(),
+                AdminPacket::UpdateFrequency(AdminUpdateFrequencyPacket {
+                    update_type: AdminUpdateType::Console,
+                    frequency: AdminUpdateFrequency::AUTOMATIC,
+                }),
+            ),
+            (
+                hex!("0800 03 01 ffffffff").into(),
+                AdminPacket::Poll(AdminPollPacket {
+                    update_type: AdminUpdateType::ClientInfo,
+                    data: u32::MAX,
+                }),
+            ),
+            (
+                hex!("0c00 04 03 00 00000000 686900").into(),
+                AdminPacket::Chat(AdminChatPacket {
+                    action: NetworkAction::Chat,
+                    dest_type: DestType::Broadcast,
+                    dest: 0,
+                    message: CString::new("hi").unwrap(),
+                }),
+            ),
+            (
+                hex!("0800 05 696e666f00").into(),
+                AdminPacket::Rcon(AdminRconPacket {
+                    command: CString::new("info").unwrap(),
+                }),
+            ),
+            (
+                hex!("0600 06 7b7d00").into(),
+                AdminPacket::Gamescript(AdminGamescriptPacket {
+                    json: CString::new("{}").unwrap(),
+                }),
+            ),
+            (
+                hex!("0700 07 2a000000").into(),
+                AdminPacket::Ping(AdminPingPacket { payload: 42 }),
+            ),
+            (
+                hex!("1200 08 49524300 0100 616c69636500 686900").into(),
+                AdminPacket::ExternalChat(AdminExternalChatPacket {
+                    source: CString::new("IRC").unwrap(),
+                    colour: 1,
+                    user: CString::new("alice").unwrap(),
+                    message: CString::new("hi").unwrap(),
+                }),
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_parse_admin_packet() {
+        for (input, expectation) in fixtures() {
+            let result = AdminPacket::from_bytes(&input).unwrap();
+
+            assert_eq!(expectation, result.1);
+        }
+    }
+
+    #[test]
+    fn test_write_admin_packet() {
+        for (expectation, input) in fixtures() {
+            let result = input.to_bytes().unwrap();
+
+            assert_eq!(expectation, result);
+        }
+    }
+
+    #[test]
+    fn test_gamescript_json_length() {
+        assert!(AdminGamescriptPacket::new(vec![b'1'; GAMESCRIPT_JSON_LENGTH - 1]).is_ok());
+        assert!(AdminGamescriptPacket::new(vec![b'1'; GAMESCRIPT_JSON_LENGTH]).is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_gamescript_json_value() {
+        let value = serde_json::json!({ "action": "score", "company": 1, "points": 42 });
+
+        let packet = AdminGamescriptPacket::encode(&value).unwrap();
+        let result: serde_json::Value = packet.decode().unwrap();
+
+        assert_eq!(value, result);
+    }
+}
@@ -0,0 +1,1228 @@
+use crate::{
+    admin_packet::{
+        admin_update_frequency, admin_update_type, AdminGamescriptPacket, AdminUpdateFrequency,
+        AdminUpdateType,
+    },
+    chat::*,
+    client_info::*,
+    game_date::*,
+    network_error::*,
+    rcon::ServerRconPacket,
+    server_detail_info::NetworkVehicleType,
+    util::*,
+};
+use byteorder::{LittleEndian, WriteBytesExt};
+use enum_map::{enum_map, EnumMap};
+use nom::{
+    self,
+    bytes::complete::take,
+    combinator::{map, map_opt, verify},
+    multi::{count, many_till},
+    number::complete::*,
+    sequence::{preceded, tuple},
+    *,
+};
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+use std::{collections::BTreeMap, ffi::CString};
+use strum::EnumDiscriminants;
+
+/// Server packet types are numbered from here on, admin packet types from zero
+const SERVER_PACKET_OFFSET: u8 = 100;
+
+fn list_end(input: &[u8]) -> IResult<&[u8], u8> {
+    verify(le_u8, |&v| v == 0).parse(input)
+}
+
+fn list_next(input: &[u8]) -> IResult<&[u8], u8> {
+    verify(le_u8, |&v| v != 0).parse(input)
+}
+
+/// Packet sent by the server to an admin over the admin port
+#[derive(Clone, Debug, PartialEq, EnumDiscriminants)]
+#[strum_discriminants(repr(u8))]
+#[strum_discriminants(derive(IntoPrimitive, TryFromPrimitive))]
+pub enum AdminServerPacket {
+    Full,
+    Banned,
+    Error(AdminServerErrorPacket),
+    Protocol(AdminServerProtocolPacket),
+    Welcome(AdminServerWelcomePacket),
+    Newgame,
+    Shutdown,
+    Date(AdminServerDatePacket),
+    ClientJoin(AdminServerClientIdPacket),
+    ClientInfo(AdminServerClientInfoPacket),
+    ClientUpdate(AdminServerClientUpdatePacket),
+    ClientQuit(AdminServerClientIdPacket),
+    ClientError(AdminServerClientErrorPacket),
+    CompanyNew(AdminServerCompanyIdPacket),
+    CompanyInfo(AdminServerCompanyInfoPacket),
+    CompanyUpdate(AdminServerCompanyUpdatePacket),
+    CompanyRemove(AdminServerCompanyRemovePacket),
+    CompanyEconomy(AdminServerCompanyEconomyPacket),
+    CompanyStats(AdminServerCompanyStatsPacket),
+    Chat(AdminServerChatPacket),
+    Rcon(ServerRconPacket),
+    Console(AdminServerConsolePacket),
+    CmdNames(AdminServerCmdNamesPacket),
+    /// Command logging in the format of admin protocol version 1
+    CmdLoggingOld,
+    Gamescript(AdminGamescriptPacket),
+    RconEnd(AdminServerRconEndPacket),
+    Pong(AdminServerPongPacket),
+    CmdLogging(AdminServerCmdLoggingPacket),
+}
+
+impl AdminServerPacket {
+    /// Parse a single size-prefixed admin packet sent by the server
+    pub fn from_bytes(input: &[u8]) -> IResult<&[u8], AdminServerPacket> {
+        let (input, size) = le_u16(input)?;
+        let (input, body) = take(usize::from(size).saturating_sub(2)).parse(input)?;
+        let (payload, packet_type) = map_opt(le_u8, |v| {
+            AdminServerPacketDiscriminants::try_from(v.checked_sub(SERVER_PACKET_OFFSET)?).ok()
+        })
+        .parse(body)?;
+
+        use AdminServerPacketDiscriminants as D;
+        let packet = match packet_type {
+            D::Full => Self::Full,
+            D::Banned => Self::Banned,
+            D::Error => {
+                map(AdminServerErrorPacket::from_bytes, Self::Error)
+                    .parse(payload)?
+                    .1
+            }
+            D::Protocol => {
+                map(AdminServerProtocolPacket::from_bytes, Self::Protocol)
+                    .parse(payload)?
+                    .1
+            }
+            D::Welcome => {
+                map(AdminServerWelcomePacket::from_bytes, Self::Welcome)
+                    .parse(payload)?
+                    .1
+            }
+            D::Newgame => Self::Newgame,
+            D::Shutdown => Self::Shutdown,
+            D::Date => {
+                map(AdminServerDatePacket::from_bytes, Self::Date)
+                    .parse(payload)?
+                    .1
+            }
+            D::ClientJoin => {
+                map(AdminServerClientIdPacket::from_bytes, Self::ClientJoin)
+                    .parse(payload)?
+                    .1
+            }
+            D::ClientInfo => {
+                map(AdminServerClientInfoPacket::from_bytes, Self::ClientInfo)
+                    .parse(payload)?
+                    .1
+            }
+            D::ClientUpdate => {
+                map(
+                    AdminServerClientUpdatePacket::from_bytes,
+                    Self::ClientUpdate,
+                )
+                .parse(payload)?
+                .1
+            }
+            D::ClientQuit => {
+                map(AdminServerClientIdPacket::from_bytes, Self::ClientQuit)
+                    .parse(payload)?
+                    .1
+            }
+            D::ClientError => {
+                map(AdminServerClientErrorPacket::from_bytes, Self::ClientError)
+                    .parse(payload)?
+                    .1
+            }
+            D::CompanyNew => {
+                map(AdminServerCompanyIdPacket::from_bytes, Self::CompanyNew)
+                    .parse(payload)?
+                    .1
+            }
+            D::CompanyInfo => {
+                map(AdminServerCompanyInfoPacket::from_bytes, Self::CompanyInfo)
+                    .parse(payload)?
+                    .1
+            }
+            D::CompanyUpdate => {
+                map(
+                    AdminServerCompanyUpdatePacket::from_bytes,
+                    Self::CompanyUpdate,
+                )
+                .parse(payload)?
+                .1
+            }
+            D::CompanyRemove => {
+                map(
+                    AdminServerCompanyRemovePacket::from_bytes,
+                    Self::CompanyRemove,
+                )
+                .parse(payload)?
+                .1
+            }
+            D::CompanyEconomy => {
+                map(
+                    AdminServerCompanyEconomyPacket::from_bytes,
+                    Self::CompanyEconomy,
+                )
+                .parse(payload)?
+                .1
+            }
+            D::CompanyStats => {
+                map(
+                    AdminServerCompanyStatsPacket::from_bytes,
+                    Self::CompanyStats,
+                )
+                .parse(payload)?
+                .1
+            }
+            D::Chat => {
+                map(AdminServerChatPacket::from_bytes, Self::Chat)
+                    .parse(payload)?
+                    .1
+            }
+            D::Rcon => {
+                map(ServerRconPacket::from_bytes, Self::Rcon)
+                    .parse(payload)?
+                    .1
+            }
+            D::Console => {
+                map(AdminServerConsolePacket::from_bytes, Self::Console)
+                    .parse(payload)?
+                    .1
+            }
+            D::CmdNames => {
+                map(AdminServerCmdNamesPacket::from_bytes, Self::CmdNames)
+                    .parse(payload)?
+                    .1
+            }
+            D::CmdLoggingOld => Self::CmdLoggingOld,
+            D::Gamescript => {
+                map(AdminGamescriptPacket::from_bytes, Self::Gamescript)
+                    .parse(payload)?
+                    .1
+            }
+            D::RconEnd => {
+                map(AdminServerRconEndPacket::from_bytes, Self::RconEnd)
+                    .parse(payload)?
+                    .1
+            }
+            D::Pong => {
+                map(AdminServerPongPacket::from_bytes, Self::Pong)
+                    .parse(payload)?
+                    .1
+            }
+            D::CmdLogging => {
+                map(AdminServerCmdLoggingPacket::from_bytes, Self::CmdLogging)
+                    .parse(payload)?
+                    .1
+            }
+        };
+
+        Ok((input, packet))
+    }
+
+    /// Update type an admin has to subscribe to or poll to receive this packet
+    pub fn update_type(&self) -> Option<AdminUpdateType> {
+        Some(match self {
+            Self::Date(_) => AdminUpdateType::Date,
+            Self::ClientJoin(_)
+            | Self::ClientInfo(_)
+            | Self::ClientUpdate(_)
+            | Self::ClientQuit(_)
+            | Self::ClientError(_) => AdminUpdateType::ClientInfo,
+            Self::CompanyNew(_)
+            | Self::CompanyInfo(_)
+            | Self::CompanyUpdate(_)
+            | Self::CompanyRemove(_) => AdminUpdateType::CompanyInfo,
+            Self::CompanyEconomy(_) => AdminUpdateType::CompanyEconomy,
+            Self::CompanyStats(_) => AdminUpdateType::CompanyStats,
+            Self::Chat(_) => AdminUpdateType::Chat,
+            Self::Console(_) => AdminUpdateType::Console,
+            Self::CmdNames(_) => AdminUpdateType::CmdNames,
+            Self::CmdLoggingOld | Self::CmdLogging(_) => AdminUpdateType::CmdLogging,
+            Self::Gamescript(_) => AdminUpdateType::Gamescript,
+            _ => return None,
+        })
+    }
+
+    pub fn to_bytes(&self) -> std::io::Result<Vec<u8>> {
+        let buf = &mut vec![];
+        buf.push(u8::from(AdminServerPacketDiscriminants::from(self)) + SERVER_PACKET_OFFSET);
+
+        match self {
+            AdminServerPacket::Full
+            | AdminServerPacket::Banned
+            | AdminServerPacket::Newgame
+            | AdminServerPacket::Shutdown
+            | AdminServerPacket::CmdLoggingOld => {}
+            AdminServerPacket::Error(data) => data.write_pkt(buf)?,
+            AdminServerPacket::Protocol(data) => data.write_pkt(buf)?,
+            AdminServerPacket::Welcome(data) => data.write_pkt(buf)?,
+            AdminServerPacket::Date(data) => data.write_pkt(buf)?,
+            AdminServerPacket::ClientJoin(data) => data.write_pkt(buf)?,
+            AdminServerPacket::ClientInfo(data) => data.write_pkt(buf)?,
+            AdminServerPacket::ClientUpdate(data) => data.write_pkt(buf)?,
+            AdminServerPacket::ClientQuit(data) => data.write_pkt(buf)?,
+            AdminServerPacket::ClientError(data) => data.write_pkt(buf)?,
+            AdminServerPacket::CompanyNew(data) => data.write_pkt(buf)?,
+            AdminServerPacket::CompanyInfo(data) => data.write_pkt(buf)?,
+            AdminServerPacket::CompanyUpdate(data) => data.write_pkt(buf)?,
+            AdminServerPacket::CompanyRemove(data) => data.write_pkt(buf)?,
+            AdminServerPacket::CompanyEconomy(data) => data.write_pkt(buf)?,
+            AdminServerPacket::CompanyStats(data) => data.write_pkt(buf)?,
+            AdminServerPacket::Chat(data) => data.write_pkt(buf)?,
+            AdminServerPacket::Rcon(data) => data.write_pkt(buf)?,
+            AdminServerPacket::Console(data) => data.write_pkt(buf)?,
+            AdminServerPacket::CmdNames(data) => data.write_pkt(buf)?,
+            AdminServerPacket::Gamescript(data) => data.write_pkt(buf)?,
+            AdminServerPacket::RconEnd(data) => data.write_pkt(buf)?,
+            AdminServerPacket::Pong(data) => data.write_pkt(buf)?,
+            AdminServerPacket::CmdLogging(data) => data.write_pkt(buf)?,
+        }
+
+        let mut out = vec![];
+        out.write_u16::<LittleEndian>(buf.len() as u16 + 2)?;
+        out.append(buf);
+
+        Ok(out)
+    }
+}
+
+/// `SERVER_ERROR`: the admin connection is being closed
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdminServerErrorPacket {
+    pub error: NetworkErrorCode,
+}
+
+impl ByteWriter for AdminServerErrorPacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.write_u8(self.error.into())?;
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for AdminServerErrorPacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(network_error_code, |error| Self { error }).parse(input)
+    }
+}
+
+/// `SERVER_PROTOCOL`: admin protocol version and the frequencies allowed per update type
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdminServerProtocolPacket {
+    pub version: u8,
+    pub update_frequencies: BTreeMap<AdminUpdateType, AdminUpdateFrequency>,
+}
+
+impl AdminServerProtocolPacket {
+    /// Whether the server accepts a subscription to `update_type` at `frequency`
+    pub fn supports(&self, update_type: AdminUpdateType, frequency: AdminUpdateFrequency) -> bool {
+        !frequency.is_empty()
+            && self
+                .update_frequencies
+                .get(&update_type)
+                .is_some_and(|allowed| allowed.contains(frequency))
+    }
+}
+
+impl ByteWriter for AdminServerProtocolPacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.write_u8(self.version)?;
+        for (&update_type, &frequency) in &self.update_frequencies {
+            buf.write_u8(1)?;
+            buf.write_u16::<LittleEndian>(update_type.into())?;
+            buf.write_u16::<LittleEndian>(frequency.bits())?;
+        }
+        buf.write_u8(0)?;
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for AdminServerProtocolPacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(
+            tuple((
+                le_u8,
+                many_till(
+                    preceded(
+                        list_next,
+                        tuple((admin_update_type, admin_update_frequency)),
+                    ),
+                    list_end,
+                ),
+            )),
+            |(version, (update_frequencies, _))| Self {
+                version,
+                update_frequencies: update_frequencies.into_iter().collect(),
+            },
+        )
+        .parse(input)
+    }
+}
+
+/// `SERVER_WELCOME`: the admin is logged in; describes the current game
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdminServerWelcomePacket {
+    pub server_name: CString,
+    pub network_revision: CString,
+    pub dedicated: bool,
+    pub map_name: CString,
+    pub generation_seed: u32,
+    pub landscape: u8,
+    pub start_date: GameDate,
+    pub map_width: u16,
+    pub map_height: u16,
+}
+
+impl ByteWriter for AdminServerWelcomePacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.extend_from_slice(self.server_name.to_bytes_with_nul());
+        buf.extend_from_slice(self.network_revision.to_bytes_with_nul());
+        buf.write_u8(if self.dedicated { 1 } else { 0 })?;
+        buf.extend_from_slice(self.map_name.to_bytes_with_nul());
+        buf.write_u32::<LittleEndian>(self.generation_seed)?;
+        buf.write_u8(self.landscape)?;
+        buf.write_u32::<LittleEndian>(self.start_date.0)?;
+        buf.write_u16::<LittleEndian>(self.map_width)?;
+        buf.write_u16::<LittleEndian>(self.map_height)?;
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for AdminServerWelcomePacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(
+            tuple((
+                read_cstring,
+                read_cstring,
+                boolean,
+                read_cstring,
+                le_u32,
+                le_u8,
+                game_date,
+                le_u16,
+                le_u16,
+            )),
+            |(
+                server_name,
+                network_revision,
+                dedicated,
+                map_name,
+                generation_seed,
+                landscape,
+                start_date,
+                map_width,
+                map_height,
+            )| Self {
+                server_name,
+                network_revision,
+                dedicated,
+                map_name,
+                generation_seed,
+                landscape,
+                start_date,
+                map_width,
+                map_height,
+            },
+        )
+        .parse(input)
+    }
+}
+
+/// `SERVER_DATE`: current game date
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdminServerDatePacket {
+    pub date: GameDate,
+}
+
+impl ByteWriter for AdminServerDatePacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.write_u32::<LittleEndian>(self.date.0)?;
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for AdminServerDatePacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(game_date, |date| Self { date }).parse(input)
+    }
+}
+
+/// `SERVER_CLIENT_JOIN` and `SERVER_CLIENT_QUIT`
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdminServerClientIdPacket {
+    pub client_id: ClientID,
+}
+
+impl ByteWriter for AdminServerClientIdPacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.write_u32::<LittleEndian>(self.client_id.0)?;
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for AdminServerClientIdPacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(client_id, |client_id| Self { client_id }).parse(input)
+    }
+}
+
+/// `SERVER_CLIENT_INFO`
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdminServerClientInfoPacket {
+    pub client_id: ClientID,
+    pub address: CString,
+    pub name: CString,
+    pub language: u8,
+    pub join_date: GameDate,
+    pub company: CompanyID,
+}
+
+impl ByteWriter for AdminServerClientInfoPacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.write_u32::<LittleEndian>(self.client_id.0)?;
+        buf.extend_from_slice(self.address.to_bytes_with_nul());
+        buf.extend_from_slice(self.name.to_bytes_with_nul());
+        buf.write_u8(self.language)?;
+        buf.write_u32::<LittleEndian>(self.join_date.0)?;
+        buf.write_u8(self.company.0)?;
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for AdminServerClientInfoPacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(
+            tuple((
+                client_id,
+                read_cstring,
+                read_cstring,
+                le_u8,
+                game_date,
+                company_id,
+            )),
+            |(client_id, address, name, language, join_date, company)| Self {
+                client_id,
+                address,
+                name,
+                language,
+                join_date,
+                company,
+            },
+        )
+        .parse(input)
+    }
+}
+
+/// `SERVER_CLIENT_UPDATE`: a client changed name or company
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdminServerClientUpdatePacket {
+    pub client_id: ClientID,
+    pub name: CString,
+    pub company: CompanyID,
+}
+
+impl ByteWriter for AdminServerClientUpdatePacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.write_u32::<LittleEndian>(self.client_id.0)?;
+        buf.extend_from_slice(self.name.to_bytes_with_nul());
+        buf.write_u8(self.company.0)?;
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for AdminServerClientUpdatePacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(
+            tuple((client_id, read_cstring, company_id)),
+            |(client_id, name, company)| Self {
+                client_id,
+                name,
+                company,
+            },
+        )
+        .parse(input)
+    }
+}
+
+/// `SERVER_CLIENT_ERROR`: a client was disconnected because of an error
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdminServerClientErrorPacket {
+    pub client_id: ClientID,
+    pub error: NetworkErrorCode,
+}
+
+impl ByteWriter for AdminServerClientErrorPacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.write_u32::<LittleEndian>(self.client_id.0)?;
+        buf.write_u8(self.error.into())?;
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for AdminServerClientErrorPacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(
+            tuple((client_id, network_error_code)),
+            |(client_id, error)| Self { client_id, error },
+        )
+        .parse(input)
+    }
+}
+
+/// `SERVER_COMPANY_NEW`
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdminServerCompanyIdPacket {
+    pub company: CompanyID,
+}
+
+impl ByteWriter for AdminServerCompanyIdPacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.write_u8(self.company.0)?;
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for AdminServerCompanyIdPacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(company_id, |company| Self { company }).parse(input)
+    }
+}
+
+/// `SERVER_COMPANY_INFO`
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdminServerCompanyInfoPacket {
+    pub company: CompanyID,
+    pub name: CString,
+    pub president_name: CString,
+    pub colour: u8,
+    pub has_password: bool,
+    pub inaugurated_year: u32,
+    pub is_ai: bool,
+    pub months_of_bankruptcy: u8,
+    pub share_owners: [CompanyID; 4],
+}
+
+impl ByteWriter for AdminServerCompanyInfoPacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.write_u8(self.company.0)?;
+        buf.extend_from_slice(self.name.to_bytes_with_nul());
+        buf.extend_from_slice(self.president_name.to_bytes_with_nul());
+        buf.write_u8(self.colour)?;
+        buf.write_u8(if self.has_password { 1 } else { 0 })?;
+        buf.write_u32::<LittleEndian>(self.inaugurated_year)?;
+        buf.write_u8(if self.is_ai { 1 } else { 0 })?;
+        buf.write_u8(self.months_of_bankruptcy)?;
+        for owner in self.share_owners {
+            buf.write_u8(owner.0)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for AdminServerCompanyInfoPacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(
+            tuple((
+                company_id,
+                read_cstring,
+                read_cstring,
+                le_u8,
+                boolean,
+                le_u32,
+                boolean,
+                le_u8,
+                tuple((company_id, company_id, company_id, company_id)),
+            )),
+            |(
+                company,
+                name,
+                president_name,
+                colour,
+                has_password,
+                inaugurated_year,
+                is_ai,
+                months_of_bankruptcy,
+                (a, b, c, d),
+            )| Self {
+                company,
+                name,
+                president_name,
+                colour,
+                has_password,
+                inaugurated_year,
+                is_ai,
+                months_of_bankruptcy,
+                share_owners: [a, b, c, d],
+            },
+        )
+        .parse(input)
+    }
+}
+
+/// `SERVER_COMPANY_UPDATE`
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdminServerCompanyUpdatePacket {
+    pub company: CompanyID,
+    pub name: CString,
+    pub president_name: CString,
+    pub colour: u8,
+    pub has_password: bool,
+    pub months_of_bankruptcy: u8,
+    pub share_owners: [CompanyID; 4],
+}
+
+impl ByteWriter for AdminServerCompanyUpdatePacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.write_u8(self.company.0)?;
+        buf.extend_from_slice(self.name.to_bytes_with_nul());
+        buf.extend_from_slice(self.president_name.to_bytes_with_nul());
+        buf.write_u8(self.colour)?;
+        buf.write_u8(if self.has_password { 1 } else { 0 })?;
+        buf.write_u8(self.months_of_bankruptcy)?;
+        for owner in self.share_owners {
+            buf.write_u8(owner.0)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for AdminServerCompanyUpdatePacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(
+            tuple((
+                company_id,
+                read_cstring,
+                read_cstring,
+                le_u8,
+                boolean,
+                le_u8,
+                tuple((company_id, company_id, company_id, company_id)),
+            )),
+            |(
+                company,
+                name,
+                president_name,
+                colour,
+                has_password,
+                months_of_bankruptcy,
+                (a, b, c, d),
+            )| Self {
+                company,
+                name,
+                president_name,
+                colour,
+                has_password,
+                months_of_bankruptcy,
+                share_owners: [a, b, c, d],
+            },
+        )
+        .parse(input)
+    }
+}
+
+/// Why a company was removed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, IntoPrimitive, TryFromPrimitive)]
+#[repr(u8)]
+pub enum AdminCompanyRemoveReason {
+    Manual,
+    Autoclean,
+    Bankrupt,
+}
+
+/// `SERVER_COMPANY_REMOVE`
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdminServerCompanyRemovePacket {
+    pub company: CompanyID,
+    pub reason: AdminCompanyRemoveReason,
+}
+
+impl ByteWriter for AdminServerCompanyRemovePacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.write_u8(self.company.0)?;
+        buf.write_u8(self.reason.into())?;
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for AdminServerCompanyRemovePacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(
+            tuple((
+                company_id,
+                map_opt(le_u8, |v| AdminCompanyRemoveReason::try_from(v).ok()),
+            )),
+            |(company, reason)| Self { company, reason },
+        )
+        .parse(input)
+    }
+}
+
+/// Economy figures of a finished quarter
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdminQuarterEconomy {
+    pub company_value: i64,
+    pub performance_history: u16,
+    pub delivered_cargo: u16,
+}
+
+/// `SERVER_COMPANY_ECONOMY`
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdminServerCompanyEconomyPacket {
+    pub company: CompanyID,
+    pub money: i64,
+    pub current_loan: i64,
+    pub income: i64,
+    /// Cargo delivered in the current quarter
+    pub delivered_cargo: u16,
+    /// Last and second to last quarter
+    pub history: [AdminQuarterEconomy; 2],
+}
+
+impl ByteWriter for AdminServerCompanyEconomyPacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.write_u8(self.company.0)?;
+        buf.write_i64::<LittleEndian>(self.money)?;
+        buf.write_i64::<LittleEndian>(self.current_loan)?;
+        buf.write_i64::<LittleEndian>(self.income)?;
+        buf.write_u16::<LittleEndian>(self.delivered_cargo)?;
+        for quarter in &self.history {
+            buf.write_i64::<LittleEndian>(quarter.company_value)?;
+            buf.write_u16::<LittleEndian>(quarter.performance_history)?;
+            buf.write_u16::<LittleEndian>(quarter.delivered_cargo)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn quarter_economy(input: &[u8]) -> IResult<&[u8], AdminQuarterEconomy> {
+    map(
+        tuple((le_i64, le_u16, le_u16)),
+        |(company_value, performance_history, delivered_cargo)| AdminQuarterEconomy {
+            company_value,
+            performance_history,
+            delivered_cargo,
+        },
+    )
+    .parse(input)
+}
+
+impl PacketPayload for AdminServerCompanyEconomyPacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(
+            tuple((
+                company_id,
+                le_i64,
+                le_i64,
+                le_i64,
+                le_u16,
+                quarter_economy,
+                quarter_economy,
+            )),
+            |(company, money, current_loan, income, delivered_cargo, last, previous)| Self {
+                company,
+                money,
+                current_loan,
+                income,
+                delivered_cargo,
+                history: [last, previous],
+            },
+        )
+        .parse(input)
+    }
+}
+
+/// `SERVER_COMPANY_STATS`
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdminServerCompanyStatsPacket {
+    pub company: CompanyID,
+    pub num_vehicles: EnumMap<NetworkVehicleType, u16>,
+    pub num_stations: EnumMap<NetworkVehicleType, u16>,
+}
+
+impl ByteWriter for AdminServerCompanyStatsPacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.write_u8(self.company.0)?;
+        for coll in [&self.num_vehicles, &self.num_stations] {
+            for &v in coll.values() {
+                buf.write_u16::<LittleEndian>(v)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn vehicle_type_counts(input: &[u8]) -> IResult<&[u8], EnumMap<NetworkVehicleType, u16>> {
+    map(count(le_u16, 5), |v| {
+        enum_map! {
+            NetworkVehicleType::Train => v[0],
+            NetworkVehicleType::Lorry => v[1],
+            NetworkVehicleType::Bus => v[2],
+            NetworkVehicleType::Plane => v[3],
+            NetworkVehicleType::Ship => v[4],
+        }
+    })
+    .parse(input)
+}
+
+impl PacketPayload for AdminServerCompanyStatsPacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(
+            tuple((company_id, vehicle_type_counts, vehicle_type_counts)),
+            |(company, num_vehicles, num_stations)| Self {
+                company,
+                num_vehicles,
+                num_stations,
+            },
+        )
+        .parse(input)
+    }
+}
+
+/// `SERVER_CHAT`
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdminServerChatPacket {
+    pub action: NetworkAction,
+    pub dest_type: DestType,
+    /// Sender of the message
+    pub client_id: ClientID,
+    pub message: CString,
+    /// Amount of money for [`NetworkAction::GiveMoney`]
+    pub data: i64,
+}
+
+impl ByteWriter for AdminServerChatPacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.write_u8(self.action.into())?;
+        buf.write_u8(self.dest_type.into())?;
+        buf.write_u32::<LittleEndian>(self.client_id.0)?;
+        buf.extend_from_slice(self.message.to_bytes_with_nul());
+        buf.write_i64::<LittleEndian>(self.data)?;
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for AdminServerChatPacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(
+            tuple((network_action, dest_type, client_id, read_cstring, le_i64)),
+            |(action, dest_type, client_id, message, data)| Self {
+                action,
+                dest_type,
+                client_id,
+                message,
+                data,
+            },
+        )
+        .parse(input)
+    }
+}
+
+/// `SERVER_CONSOLE`: a line printed to the server console
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdminServerConsolePacket {
+    /// Subsystem that printed the line, e.g. "net" or "script"
+    pub origin: CString,
+    pub text: CString,
+}
+
+impl ByteWriter for AdminServerConsolePacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.extend_from_slice(self.origin.to_bytes_with_nul());
+        buf.extend_from_slice(self.text.to_bytes_with_nul());
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for AdminServerConsolePacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(tuple((read_cstring, read_cstring)), |(origin, text)| Self {
+            origin,
+            text,
+        })
+        .parse(input)
+    }
+}
+
+/// `SERVER_CMD_NAMES`: names of command IDs; large tables are split over several packets
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdminServerCmdNamesPacket {
+    pub names: BTreeMap<u16, CString>,
+}
+
+impl ByteWriter for AdminServerCmdNamesPacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        for (&id, name) in &self.names {
+            buf.write_u8(1)?;
+            buf.write_u16::<LittleEndian>(id)?;
+            buf.extend_from_slice(name.to_bytes_with_nul());
+        }
+        buf.write_u8(0)?;
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for AdminServerCmdNamesPacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(
+            many_till(preceded(list_next, tuple((le_u16, read_cstring))), list_end),
+            |(names, _)| Self {
+                names: names.into_iter().collect(),
+            },
+        )
+        .parse(input)
+    }
+}
+
+/// `SERVER_RCON_END`: all output of an `ADMIN_RCON` command has been sent
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdminServerRconEndPacket {
+    pub command: CString,
+}
+
+impl ByteWriter for AdminServerRconEndPacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.extend_from_slice(self.command.to_bytes_with_nul());
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for AdminServerRconEndPacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(read_cstring, |command| Self { command }).parse(input)
+    }
+}
+
+/// `SERVER_PONG`: answer to `ADMIN_PING`
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdminServerPongPacket {
+    pub payload: u32,
+}
+
+impl ByteWriter for AdminServerPongPacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.write_u32::<LittleEndian>(self.payload)?;
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for AdminServerPongPacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(le_u32, |payload| Self { payload }).parse(input)
+    }
+}
+
+/// `SERVER_CMD_LOGGING`: a command executed by a client
+#[derive(Clone, Debug, PartialEq)]
+pub struct AdminServerCmdLoggingPacket {
+    pub client_id: ClientID,
+    pub company: CompanyID,
+    pub command: u16,
+    /// Command parameters in OpenTTD's internal serialization
+    pub data: Vec<u8>,
+    pub frame: u32,
+}
+
+impl ByteWriter for AdminServerCmdLoggingPacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.write_u32::<LittleEndian>(self.client_id.0)?;
+        buf.write_u8(self.company.0)?;
+        buf.write_u16::<LittleEndian>(self.command)?;
+        buf.write_u16::<LittleEndian>(self.data.len().try_into().map_err(|_| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, "command data too long")
+        })?)?;
+        buf.extend_from_slice(&self.data);
+        buf.write_u32::<LittleEndian>(self.frame)?;
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for AdminServerCmdLoggingPacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        let (input, (client_id, company, command, len)) =
+            tuple((client_id, company_id, le_u16, le_u16)).parse(input)?;
+        map(
+            tuple((take(len), le_u32)),
+            move |(data, frame): (&[u8], _)| Self {
+                client_id,
+                company,
+                command,
+                data: data.to_vec(),
+                frame,
+            },
+        )
+        .parse(input)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex_literal::hex;
+    use maplit::btreemap;
+
+    fn fixtures() -> Vec<(Vec<u8>, AdminServerPacket)> {
+        vec![
+            (hex!("0300 64").into(), AdminServerPacket::Full),
+            (
+                hex!("1400 67 02 01 0000 0400 01 0100 0200 01 0a00 4000 00").into(),
+                AdminServerPacket::Protocol(AdminServerProtocolPacket {
+                    version: 2,
+                    update_frequencies: btreemap! {
+                        AdminUpdateType::Date => AdminUpdateFrequency::WEEKLY,
+                        AdminUpdateType::ClientInfo => AdminUpdateFrequency::DAILY,
+                        AdminUpdateType::Unknown(10) => AdminUpdateFrequency::AUTOMATIC,
+                    },
+                }),
+            ),
+            (
+                hex!("0700 6b 63ec0a00").into(),
+                AdminServerPacket::Date(AdminServerDatePacket {
+                    date: GameDate(715875),
+                }),
+            ),
+            (
+                hex!("1800 76 01 0500 0200 0000 0100 0000 0100 0300 0400 0000 0000").into(),
+                AdminServerPacket::CompanyStats(AdminServerCompanyStatsPacket {
+                    company: CompanyID(1),
+                    num_vehicles: enum_map! {
+                        NetworkVehicleType::Train => 5,
+                        NetworkVehicleType::Lorry => 2,
+                        NetworkVehicleType::Bus => 0,
+                        NetworkVehicleType::Plane => 1,
+                        NetworkVehicleType::Ship => 0,
+                    },
+                    num_stations: enum_map! {
+                        NetworkVehicleType::Train => 1,
+                        NetworkVehicleType::Lorry => 3,
+                        NetworkVehicleType::Bus => 4,
+                        NetworkVehicleType::Plane => 0,
+                        NetworkVehicleType::Ship => 0,
+                    },
+                }),
+            ),
+            (
+                hex!(
+                    "3600 75 01 18fcffffffffffff e093040000000000 06ffffffffffffff 1000"
+                    "78ecffffffffffff 0001 1000 204e000000000000 0000 0000"
+                )
+                .into(),
+                AdminServerPacket::CompanyEconomy(AdminServerCompanyEconomyPacket {
+                    company: CompanyID(1),
+                    money: -1000,
+                    current_loan: 300000,
+                    income: -250,
+                    delivered_cargo: 16,
+                    history: [
+                        AdminQuarterEconomy {
+                            company_value: -5000,
+                            performance_history: 256,
+                            delivered_cargo: 16,
+                        },
+                        AdminQuarterEconomy {
+                            company_value: 20000,
+                            performance_history: 0,
+                            delivered_cargo: 0,
+                        },
+                    ],
+                }),
+            ),
+            (
+                hex!("1300 77 06 02 03000000 7800 0cfeffffffffffff").into(),
+                AdminServerPacket::Chat(AdminServerChatPacket {
+                    action: NetworkAction::GiveMoney,
+                    dest_type: DestType::Client,
+                    client_id: ClientID(3),
+                    message: CString::new("x").unwrap(),
+                    data: -500,
+                }),
+            ),
+            (
+                hex!("1500 7a 01 0000 4275696c6400 01 0100 4d6f766500 00").into(),
+                AdminServerPacket::CmdNames(AdminServerCmdNamesPacket {
+                    names: btreemap! {
+                        0 => CString::new("Build").unwrap(),
+                        1 => CString::new("Move").unwrap(),
+                    },
+                }),
+            ),
+            (
+                hex!("0800 7d 696e666f00").into(),
+                AdminServerPacket::RconEnd(AdminServerRconEndPacket {
+                    command: CString::new("info").unwrap(),
+                }),
+            ),
+            (
+                hex!("1200 7f 05000000 00 0300 0200 abcd 64000000").into(),
+                AdminServerPacket::CmdLogging(AdminServerCmdLoggingPacket {
+                    client_id: ClientID(5),
+                    company: CompanyID(0),
+                    command: 3,
+                    data: vec![0xab, 0xcd],
+                    frame: 100,
+                }),
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_parse_admin_server_packet() {
+        for (input, expectation) in fixtures() {
+            let result = AdminServerPacket::from_bytes(&input).unwrap();
+
+            assert_eq!(expectation, result.1);
+        }
+    }
+
+    #[test]
+    fn test_write_admin_server_packet() {
+        for (expectation, input) in fixtures() {
+            let result = input.to_bytes().unwrap();
+
+            assert_eq!(expectation, result);
+        }
+    }
+
+    #[test]
+    fn test_protocol_supports() {
+        let protocol = AdminServerProtocolPacket {
+            version: 3,
+            update_frequencies: btreemap! {
+                AdminUpdateType::Date => AdminUpdateFrequency::POLL
+                    | AdminUpdateFrequency::DAILY
+                    | AdminUpdateFrequency::WEEKLY,
+                AdminUpdateType::Chat => AdminUpdateFrequency::AUTOMATIC,
+            },
+        };
+
+        assert!(protocol.supports(AdminUpdateType::Date, AdminUpdateFrequency::DAILY));
+        assert!(protocol.supports(AdminUpdateType::Chat, AdminUpdateFrequency::AUTOMATIC));
+        assert!(!protocol.supports(AdminUpdateType::Date, AdminUpdateFrequency::AUTOMATIC));
+        assert!(!protocol.supports(AdminUpdateType::Date, AdminUpdateFrequency::empty()));
+        assert!(!protocol.supports(AdminUpdateType::Console, AdminUpdateFrequency::POLL));
+    }
+}
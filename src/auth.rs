@@ -0,0 +1,463 @@
+use crate::util::*;
+use anyhow::{bail, ensure, format_err};
+use blake2::{Blake2b512, Digest};
+use byteorder::{LittleEndian, WriteBytesExt};
+use chacha20::{
+    cipher::{consts::U10, KeyIvInit, StreamCipher},
+    hchacha, ChaCha20,
+};
+use chacha20poly1305::{
+    aead::AeadInPlace, ChaCha20Poly1305, KeyInit, Tag, XChaCha20Poly1305, XNonce,
+};
+use nom::{
+    self,
+    bytes::complete::take,
+    combinator::{map, map_opt},
+    number::complete::*,
+    sequence::tuple,
+    *,
+};
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+use rand_core::{OsRng, RngCore};
+use x25519_dalek::{PublicKey, StaticSecret};
+
+pub const X25519_KEY_SIZE: usize = 32;
+pub const NONCE_SIZE: usize = 24;
+pub const MAC_SIZE: usize = 16;
+/// Size of the random message a client encrypts to prove it derived the right keys
+const AUTH_MESSAGE_SIZE: usize = 8;
+
+fn byte_array<const N: usize>(input: &[u8]) -> IResult<&[u8], [u8; N]> {
+    map(take(N), |v: &[u8]| v.try_into().unwrap()).parse(input)
+}
+
+/// How the server wants the client to authenticate
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, IntoPrimitive, TryFromPrimitive)]
+#[repr(u8)]
+pub enum NetworkAuthenticationMethod {
+    /// Plain key exchange, used to set up encryption when no password is configured
+    X25519KeyExchangeOnly,
+    /// Key exchange with the server password mixed into the derived keys
+    X25519Pake,
+    /// The client's long-term public key has to be on the server's allow list
+    X25519AuthorizedKey,
+}
+
+/// `SERVER_AUTH_REQUEST`
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerAuthRequestPacket {
+    pub method: NetworkAuthenticationMethod,
+    pub public_key: [u8; X25519_KEY_SIZE],
+    pub key_exchange_nonce: [u8; NONCE_SIZE],
+}
+
+impl ByteWriter for ServerAuthRequestPacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.write_u8(self.method.into())?;
+        buf.extend_from_slice(&self.public_key);
+        buf.extend_from_slice(&self.key_exchange_nonce);
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for ServerAuthRequestPacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(
+            tuple((
+                map_opt(le_u8, |v| NetworkAuthenticationMethod::try_from(v).ok()),
+                byte_array,
+                byte_array,
+            )),
+            |(method, public_key, key_exchange_nonce)| Self {
+                method,
+                public_key,
+                key_exchange_nonce,
+            },
+        )
+        .parse(input)
+    }
+}
+
+/// `CLIENT_AUTH_RESPONSE`
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClientAuthResponsePacket {
+    pub public_key: [u8; X25519_KEY_SIZE],
+    pub mac: [u8; MAC_SIZE],
+    pub message: [u8; AUTH_MESSAGE_SIZE],
+}
+
+impl ByteWriter for ClientAuthResponsePacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.extend_from_slice(&self.public_key);
+        buf.extend_from_slice(&self.mac);
+        buf.extend_from_slice(&self.message);
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for ClientAuthResponsePacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(
+            tuple((byte_array, byte_array, byte_array)),
+            |(public_key, mac, message)| Self {
+                public_key,
+                mac,
+                message,
+            },
+        )
+        .parse(input)
+    }
+}
+
+/// `SERVER_ENABLE_ENCRYPTION`: authentication completed, everything after this packet is encrypted
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerEnableEncryptionPacket {
+    pub encryption_nonce: [u8; NONCE_SIZE],
+}
+
+impl ByteWriter for ServerEnableEncryptionPacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.extend_from_slice(&self.encryption_nonce);
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for ServerEnableEncryptionPacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(byte_array, |encryption_nonce| Self { encryption_nonce }).parse(input)
+    }
+}
+
+/// Keys for both directions of an encrypted connection
+#[derive(Clone, Debug, PartialEq)]
+pub struct AuthKeys {
+    pub client_to_server: [u8; 32],
+    pub server_to_client: [u8; 32],
+}
+
+/// Derive the connection keys from an X25519 exchange.
+///
+/// `extra_payload` is the password for [`NetworkAuthenticationMethod::X25519Pake`] and empty
+/// otherwise, so a wrong password yields keys that fail to decrypt the auth response.
+pub fn derive_keys(
+    our_secret: &StaticSecret,
+    peer_public: &PublicKey,
+    server_public: &PublicKey,
+    client_public: &PublicKey,
+    extra_payload: &[u8],
+) -> anyhow::Result<AuthKeys> {
+    let shared_secret = our_secret.diffie_hellman(peer_public);
+    ensure!(
+        shared_secret.was_contributory(),
+        "key exchange produced a weak shared secret"
+    );
+
+    let digest = Blake2b512::new()
+        .chain_update(shared_secret.as_bytes())
+        .chain_update(server_public.as_bytes())
+        .chain_update(client_public.as_bytes())
+        .chain_update(extra_payload)
+        .finalize();
+
+    Ok(AuthKeys {
+        client_to_server: digest[..32].try_into().unwrap(),
+        server_to_client: digest[32..].try_into().unwrap(),
+    })
+}
+
+/// Answer a `SERVER_AUTH_REQUEST`.
+///
+/// `secret` is the long-term key for [`NetworkAuthenticationMethod::X25519AuthorizedKey`];
+/// the other methods use a fresh key when it is `None`.
+pub fn client_auth_response(
+    request: &ServerAuthRequestPacket,
+    secret: Option<&StaticSecret>,
+    password: &str,
+) -> anyhow::Result<(ClientAuthResponsePacket, AuthKeys)> {
+    let secret = match (request.method, secret) {
+        (_, Some(secret)) => secret.clone(),
+        (NetworkAuthenticationMethod::X25519AuthorizedKey, None) => {
+            bail!("server requires an authorized key")
+        }
+        (_, None) => StaticSecret::random_from_rng(OsRng),
+    };
+    let extra_payload = match request.method {
+        NetworkAuthenticationMethod::X25519Pake => password.as_bytes(),
+        _ => &[],
+    };
+
+    let public_key = PublicKey::from(&secret);
+    let server_public = PublicKey::from(request.public_key);
+    let keys = derive_keys(
+        &secret,
+        &server_public,
+        &server_public,
+        &public_key,
+        extra_payload,
+    )?;
+
+    let mut message = [0; AUTH_MESSAGE_SIZE];
+    OsRng.fill_bytes(&mut message);
+    let mac = XChaCha20Poly1305::new(&keys.client_to_server.into())
+        .encrypt_in_place_detached(
+            XNonce::from_slice(&request.key_exchange_nonce),
+            public_key.as_bytes(),
+            &mut message,
+        )
+        .map_err(|_| format_err!("failed to encrypt auth response"))?;
+
+    Ok((
+        ClientAuthResponsePacket {
+            public_key: public_key.to_bytes(),
+            mac: mac.into(),
+            message,
+        },
+        keys,
+    ))
+}
+
+/// Check a `CLIENT_AUTH_RESPONSE` on the server side and derive the connection keys
+pub fn verify_auth_response(
+    request: &ServerAuthRequestPacket,
+    server_secret: &StaticSecret,
+    response: &ClientAuthResponsePacket,
+    password: &str,
+) -> anyhow::Result<AuthKeys> {
+    let extra_payload = match request.method {
+        NetworkAuthenticationMethod::X25519Pake => password.as_bytes(),
+        _ => &[],
+    };
+
+    let client_public = PublicKey::from(response.public_key);
+    let keys = derive_keys(
+        server_secret,
+        &client_public,
+        &PublicKey::from(server_secret),
+        &client_public,
+        extra_payload,
+    )?;
+
+    let mut message = response.message;
+    XChaCha20Poly1305::new(&keys.client_to_server.into())
+        .decrypt_in_place_detached(
+            XNonce::from_slice(&request.key_exchange_nonce),
+            &response.public_key,
+            &mut message,
+            Tag::from_slice(&response.mac),
+        )
+        .map_err(|_| format_err!("authentication failed"))?;
+
+    Ok(keys)
+}
+
+/// One direction of an encrypted connection.
+///
+/// This is Monocypher's incremental XChaCha20-Poly1305 as used by OpenTTD: every message is
+/// sealed like a single-shot AEAD message and the key is then replaced by the unused half of
+/// the Poly1305 key block, so messages must be processed in the order they were sent.
+#[derive(Clone)]
+pub struct PacketCipher {
+    key: [u8; 32],
+    nonce: [u8; 12],
+}
+
+impl PacketCipher {
+    pub fn new(key: &[u8; 32], nonce: &[u8; NONCE_SIZE]) -> Self {
+        let subkey = hchacha::<U10>(key.into(), nonce[..16].into());
+        let mut chacha_nonce = [0; 12];
+        chacha_nonce[4..].copy_from_slice(&nonce[16..]);
+
+        Self {
+            key: subkey.into(),
+            nonce: chacha_nonce,
+        }
+    }
+
+    fn rekey(&mut self) {
+        let mut block = [0; 64];
+        ChaCha20::new(&self.key.into(), &self.nonce.into()).apply_keystream(&mut block);
+        self.key.copy_from_slice(&block[32..]);
+    }
+
+    /// Encrypt in place and return the MAC
+    pub fn encrypt(&mut self, message: &mut [u8]) -> [u8; MAC_SIZE] {
+        let mac = ChaCha20Poly1305::new(&self.key.into())
+            .encrypt_in_place_detached(&self.nonce.into(), &[], message)
+            .unwrap();
+        self.rekey();
+
+        mac.into()
+    }
+
+    /// Verify the MAC and decrypt in place
+    pub fn decrypt(&mut self, mac: &[u8; MAC_SIZE], message: &mut [u8]) -> anyhow::Result<()> {
+        ChaCha20Poly1305::new(&self.key.into())
+            .decrypt_in_place_detached(&self.nonce.into(), &[], message, mac.into())
+            .map_err(|_| format_err!("packet failed authentication"))?;
+        self.rekey();
+
+        Ok(())
+    }
+}
+
+/// Encrypts and decrypts size-prefixed packets once `SERVER_ENABLE_ENCRYPTION` was exchanged.
+///
+/// An encrypted packet is the size, the MAC, then the encrypted packet type and payload.
+#[derive(Clone)]
+pub struct EncryptedTransport {
+    send: PacketCipher,
+    recv: PacketCipher,
+}
+
+impl EncryptedTransport {
+    pub fn client(keys: &AuthKeys, encryption_nonce: &[u8; NONCE_SIZE]) -> Self {
+        Self {
+            send: PacketCipher::new(&keys.client_to_server, encryption_nonce),
+            recv: PacketCipher::new(&keys.server_to_client, encryption_nonce),
+        }
+    }
+
+    pub fn server(keys: &AuthKeys, encryption_nonce: &[u8; NONCE_SIZE]) -> Self {
+        Self {
+            send: PacketCipher::new(&keys.server_to_client, encryption_nonce),
+            recv: PacketCipher::new(&keys.client_to_server, encryption_nonce),
+        }
+    }
+
+    /// Encrypt a plain packet such as the output of [`GamePacket::to_bytes_with_version`](crate::GamePacket::to_bytes_with_version)
+    pub fn seal(&mut self, frame: &[u8]) -> std::io::Result<Vec<u8>> {
+        if frame.len() < 3 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "packet too short",
+            ));
+        }
+        let size = u16::try_from(frame.len() + MAC_SIZE).map_err(|_| {
+            std::io::Error::new(std::io::ErrorKind::InvalidInput, "packet too large")
+        })?;
+
+        let mut message = frame[2..].to_vec();
+        let mac = self.send.encrypt(&mut message);
+
+        let mut out = Vec::with_capacity(usize::from(size));
+        out.write_u16::<LittleEndian>(size)?;
+        out.extend_from_slice(&mac);
+        out.append(&mut message);
+
+        Ok(out)
+    }
+
+    /// Decrypt a whole encrypted packet back into its plain size-prefixed form
+    pub fn open(&mut self, frame: &[u8]) -> anyhow::Result<Vec<u8>> {
+        ensure!(frame.len() > 2 + MAC_SIZE, "encrypted packet too short");
+        ensure!(
+            usize::from(u16::from_le_bytes([frame[0], frame[1]])) == frame.len(),
+            "encrypted packet size mismatch"
+        );
+
+        let mac = frame[2..2 + MAC_SIZE].try_into().unwrap();
+        let mut message = frame[2 + MAC_SIZE..].to_vec();
+        self.recv.decrypt(mac, &mut message)?;
+
+        let mut out = Vec::with_capacity(message.len() + 2);
+        out.write_u16::<LittleEndian>(message.len() as u16 + 2)?;
+        out.append(&mut message);
+
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ClientRconPacket, GamePacket, GameProtocolVersion::V14};
+    use std::ffi::CString;
+
+    fn handshake(
+        method: NetworkAuthenticationMethod,
+        server_password: &str,
+        client_password: &str,
+    ) -> anyhow::Result<(AuthKeys, AuthKeys)> {
+        let server_secret = StaticSecret::random_from_rng(OsRng);
+        let request = ServerAuthRequestPacket {
+            method,
+            public_key: PublicKey::from(&server_secret).to_bytes(),
+            key_exchange_nonce: [7; NONCE_SIZE],
+        };
+
+        let (response, client_keys) = client_auth_response(&request, None, client_password)?;
+
+        // Go through the wire format on the way to the server
+        let bytes = GamePacket::ClientAuthResponse(response).to_bytes_with_version(V14)?;
+        let response = match GamePacket::from_bytes_with_version(&bytes, V14).unwrap().1 {
+            GamePacket::ClientAuthResponse(response) => response,
+            other => panic!("unexpected packet {:?}", other),
+        };
+
+        let server_keys =
+            verify_auth_response(&request, &server_secret, &response, server_password)?;
+
+        Ok((client_keys, server_keys))
+    }
+
+    #[test]
+    fn test_key_exchange() {
+        let (client_keys, server_keys) =
+            handshake(NetworkAuthenticationMethod::X25519Pake, "pw", "pw").unwrap();
+        assert_eq!(client_keys, server_keys);
+
+        assert!(handshake(NetworkAuthenticationMethod::X25519Pake, "pw", "wrong").is_err());
+        assert!(handshake(NetworkAuthenticationMethod::X25519KeyExchangeOnly, "", "").is_ok());
+    }
+
+    #[test]
+    fn test_first_message_is_plain_xchacha20poly1305() {
+        let key = [1; 32];
+        let nonce = [2; NONCE_SIZE];
+        let mut message = b"hello".to_vec();
+        let mac = PacketCipher::new(&key, &nonce).encrypt(&mut message);
+
+        let mut expectation = b"hello".to_vec();
+        let expected_mac = XChaCha20Poly1305::new(&key.into())
+            .encrypt_in_place_detached(XNonce::from_slice(&nonce), &[], &mut expectation)
+            .unwrap();
+
+        assert_eq!(message, expectation);
+        assert_eq!(mac, <[u8; MAC_SIZE]>::from(expected_mac));
+    }
+
+    #[test]
+    fn test_encrypted_transport() {
+        let (keys, _) =
+            handshake(NetworkAuthenticationMethod::X25519KeyExchangeOnly, "", "").unwrap();
+        let nonce = [9; NONCE_SIZE];
+        let mut client = EncryptedTransport::client(&keys, &nonce);
+        let mut server = EncryptedTransport::server(&keys, &nonce);
+
+        for command in ["info", "clients", "info"] {
+            let packet = GamePacket::ClientRcon(ClientRconPacket {
+                password: CString::default(),
+                command: CString::new(command).unwrap(),
+            });
+            let plain = packet.to_bytes_with_version(V14).unwrap();
+
+            let sealed = client.seal(&plain).unwrap();
+            assert_eq!(sealed.len(), plain.len() + MAC_SIZE);
+            assert_ne!(sealed[2 + MAC_SIZE..], plain[2..]);
+
+            let opened = server.open(&sealed).unwrap();
+            assert_eq!(
+                GamePacket::from_bytes_with_version(&opened, V14).unwrap().1,
+                packet
+            );
+        }
+
+        // Replaying a packet fails because the key has moved on
+        let sealed = client.seal(&[3, 0, 42]).unwrap();
+        server.open(&sealed).unwrap();
+        assert!(server.open(&sealed).is_err());
+    }
+}
@@ -0,0 +1,41 @@
+use nom::{self, combinator::map_opt, number::complete::*, *};
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+
+/// Kind of message shown in the chat or news log
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, IntoPrimitive, TryFromPrimitive)]
+#[repr(u8)]
+pub enum NetworkAction {
+    Join,
+    Leave,
+    ServerMessage,
+    Chat,
+    ChatCompany,
+    ChatClient,
+    GiveMoney,
+    NameChange,
+    CompanySpectator,
+    CompanyJoin,
+    CompanyNew,
+    Kicked,
+    ExternalChat,
+}
+
+pub fn network_action(input: &[u8]) -> IResult<&[u8], NetworkAction> {
+    map_opt(le_u8, |v| NetworkAction::try_from(v).ok()).parse(input)
+}
+
+/// Audience of a chat message
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, IntoPrimitive, TryFromPrimitive)]
+#[repr(u8)]
+pub enum DestType {
+    /// Everyone; the destination is ignored
+    Broadcast,
+    /// Members of the company given as destination
+    Team,
+    /// The client given as destination
+    Client,
+}
+
+pub fn dest_type(input: &[u8]) -> IResult<&[u8], DestType> {
+    map_opt(le_u8, |v| DestType::try_from(v).ok()).parse(input)
+}
@@ -0,0 +1,275 @@
+use crate::{network_error::*, util::*};
+use byteorder::{LittleEndian, WriteBytesExt};
+use nom::{self, combinator::map, number::complete::*, sequence::tuple, *};
+use std::{ffi::CString, fmt};
+
+/// Identifier the server assigns to every connected client
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ClientID(pub u32);
+
+impl ClientID {
+    pub const INVALID: Self = Self(0);
+    /// The server itself, e.g. as the sender of chat messages
+    pub const SERVER: Self = Self(1);
+    /// Lowest ID handed out to remote clients
+    pub const FIRST: Self = Self(2);
+}
+
+impl fmt::Display for ClientID {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(fmt, "#{}", self.0)
+    }
+}
+
+pub fn client_id(input: &[u8]) -> IResult<&[u8], ClientID> {
+    map(le_u32, ClientID).parse(input)
+}
+
+/// Company slot, or one of the special values a client can be in instead of a company
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CompanyID(pub u8);
+
+impl CompanyID {
+    pub const FIRST: Self = Self(0);
+    /// Number of regular company slots
+    pub const MAX_COMPANIES: u8 = 15;
+    /// Client is still joining and has not picked a company
+    pub const INACTIVE_CLIENT: Self = Self(253);
+    /// Requests the server to found a new company for the client
+    pub const NEW_COMPANY: Self = Self(254);
+    pub const SPECTATOR: Self = Self(255);
+
+    /// Whether this is a regular company rather than a special value
+    pub fn is_company(self) -> bool {
+        self.0 < Self::MAX_COMPANIES
+    }
+}
+
+impl fmt::Display for CompanyID {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match *self {
+            Self::INACTIVE_CLIENT => fmt.write_str("inactive"),
+            Self::NEW_COMPANY => fmt.write_str("new company"),
+            Self::SPECTATOR => fmt.write_str("spectator"),
+            // OpenTTD numbers companies from 1 in the user interface
+            Self(v) => write!(fmt, "company {}", u16::from(v) + 1),
+        }
+    }
+}
+
+pub fn company_id(input: &[u8]) -> IResult<&[u8], CompanyID> {
+    map(le_u8, CompanyID).parse(input)
+}
+
+/// `SERVER_CLIENT_INFO`: name and company of a client
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerClientInfoPacket {
+    pub client_id: ClientID,
+    pub company: CompanyID,
+    pub name: CString,
+}
+
+impl ByteWriter for ServerClientInfoPacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.write_u32::<LittleEndian>(self.client_id.0)?;
+        buf.write_u8(self.company.0)?;
+        buf.extend_from_slice(self.name.to_bytes_with_nul());
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for ServerClientInfoPacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(
+            tuple((client_id, company_id, read_cstring)),
+            |(client_id, company, name)| Self {
+                client_id,
+                company,
+                name,
+            },
+        )
+        .parse(input)
+    }
+}
+
+/// `CLIENT_SET_NAME`: the client renames itself
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClientSetNamePacket {
+    pub name: CString,
+}
+
+impl ByteWriter for ClientSetNamePacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.extend_from_slice(self.name.to_bytes_with_nul());
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for ClientSetNamePacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(read_cstring, |name| Self { name }).parse(input)
+    }
+}
+
+/// `SERVER_JOIN`: a client has finished joining the game
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerJoinPacket {
+    pub client_id: ClientID,
+}
+
+impl ByteWriter for ServerJoinPacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.write_u32::<LittleEndian>(self.client_id.0)?;
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for ServerJoinPacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(client_id, |client_id| Self { client_id }).parse(input)
+    }
+}
+
+/// `SERVER_QUIT`: a client has left the game
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerQuitPacket {
+    pub client_id: ClientID,
+}
+
+impl ByteWriter for ServerQuitPacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.write_u32::<LittleEndian>(self.client_id.0)?;
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for ServerQuitPacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(client_id, |client_id| Self { client_id }).parse(input)
+    }
+}
+
+/// `SERVER_ERROR_QUIT`: a client has left the game because of an error
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerErrorQuitPacket {
+    pub client_id: ClientID,
+    pub error: NetworkErrorCode,
+}
+
+impl ByteWriter for ServerErrorQuitPacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.write_u32::<LittleEndian>(self.client_id.0)?;
+        buf.write_u8(self.error.into())?;
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for ServerErrorQuitPacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(
+            tuple((client_id, network_error_code)),
+            |(client_id, error)| Self { client_id, error },
+        )
+        .parse(input)
+    }
+}
+
+/// `SERVER_MOVE`: a client has been moved to another company
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerMovePacket {
+    pub client_id: ClientID,
+    pub company: CompanyID,
+}
+
+impl ByteWriter for ServerMovePacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.write_u32::<LittleEndian>(self.client_id.0)?;
+        buf.write_u8(self.company.0)?;
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for ServerMovePacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(tuple((client_id, company_id)), |(client_id, company)| {
+            Self { client_id, company }
+        })
+        .parse(input)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::GamePacket;
+    use hex_literal::hex;
+
+    fn fixtures() -> Vec<(Vec<u8>, GamePacket)> {
+        vec![
+            (
+                hex!("0e00 11 05000000 02 416c69636500").into(),
+                GamePacket::ServerClientInfo(ServerClientInfoPacket {
+                    client_id: ClientID(5),
+                    company: CompanyID(2),
+                    name: CString::new("Alice").unwrap(),
+                }),
+            ),
+            (
+                hex!("0700 19 05000000").into(),
+                GamePacket::ServerJoin(ServerJoinPacket {
+                    client_id: ClientID(5),
+                }),
+            ),
+            (
+                hex!("0700 27 426f6200").into(),
+                GamePacket::ClientSetName(ClientSetNamePacket {
+                    name: CString::new("Bob").unwrap(),
+                }),
+            ),
+            (
+                hex!("0800 25 05000000 ff").into(),
+                GamePacket::ServerMove(ServerMovePacket {
+                    client_id: ClientID(5),
+                    company: CompanyID::SPECTATOR,
+                }),
+            ),
+            (
+                hex!("0700 2b 05000000").into(),
+                GamePacket::ServerQuit(ServerQuitPacket {
+                    client_id: ClientID(5),
+                }),
+            ),
+            (
+                hex!("0800 2d 05000000 03").into(),
+                GamePacket::ServerErrorQuit(ServerErrorQuitPacket {
+                    client_id: ClientID(5),
+                    error: NetworkErrorCode::ConnectionLost,
+                }),
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_parse_client_packets() {
+        for (input, expectation) in fixtures() {
+            let result = GamePacket::from_bytes(&input).unwrap();
+
+            assert_eq!(expectation, result.1);
+        }
+    }
+
+    #[test]
+    fn test_write_client_packets() {
+        for (expectation, input) in fixtures() {
+            let result = input.to_bytes().unwrap();
+
+            assert_eq!(expectation, result);
+        }
+    }
+}
@@ -0,0 +1,524 @@
+use crate::{
+    newgrf::{newgrf_md5, NewGRFHash},
+    util::*,
+};
+use byteorder::{LittleEndian, WriteBytesExt};
+use nom::{
+    self,
+    bytes::complete::take,
+    combinator::{map, map_opt, rest},
+    multi::length_count,
+    number::complete::*,
+    sequence::tuple,
+    *,
+};
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+use std::ffi::CString;
+use strum::EnumDiscriminants;
+
+/// Default TCP port of the content server
+pub const CONTENT_SERVER_PORT: u16 = 3978;
+
+/// Kind of content hosted on the content server (BaNaNaS)
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, IntoPrimitive, TryFromPrimitive,
+)]
+#[repr(u8)]
+pub enum ContentType {
+    BaseGraphics = 1,
+    NewGrf,
+    Ai,
+    AiLibrary,
+    Scenario,
+    Heightmap,
+    BaseSounds,
+    BaseMusic,
+    Game,
+    GameLibrary,
+}
+
+pub fn content_type(input: &[u8]) -> IResult<&[u8], ContentType> {
+    map_opt(le_u8, |v| ContentType::try_from(v).ok()).parse(input)
+}
+
+/// Packet of the content server protocol
+#[derive(Clone, Debug, PartialEq, EnumDiscriminants)]
+#[strum_discriminants(repr(u8))]
+#[strum_discriminants(derive(IntoPrimitive, TryFromPrimitive))]
+pub enum ContentPacket {
+    ClientInfoList(ClientInfoListPacket),
+    ClientInfoId(ClientContentIdsPacket),
+    ClientInfoExtid(ClientInfoExtidPacket),
+    ClientInfoExtidMd5(ClientInfoExtidMd5Packet),
+    ServerInfo(ServerInfoPacket),
+    ClientContent(ClientContentIdsPacket),
+    ServerContent(ServerContentPacket),
+}
+
+impl ContentPacket {
+    /// Parse a single size-prefixed content packet
+    pub fn from_bytes(input: &[u8]) -> IResult<&[u8], ContentPacket> {
+        let (input, size) = le_u16(input)?;
+        let (input, body) = take(usize::from(size).saturating_sub(2)).parse(input)?;
+        let (payload, packet_type) =
+            map_opt(le_u8, |v| ContentPacketDiscriminants::try_from(v).ok()).parse(body)?;
+
+        use ContentPacketDiscriminants as D;
+        let packet = match packet_type {
+            D::ClientInfoList => {
+                map(ClientInfoListPacket::from_bytes, Self::ClientInfoList)
+                    .parse(payload)?
+                    .1
+            }
+            D::ClientInfoId => {
+                map(ClientContentIdsPacket::from_bytes, Self::ClientInfoId)
+                    .parse(payload)?
+                    .1
+            }
+            D::ClientInfoExtid => {
+                map(ClientInfoExtidPacket::from_bytes, Self::ClientInfoExtid)
+                    .parse(payload)?
+                    .1
+            }
+            D::ClientInfoExtidMd5 => {
+                map(
+                    ClientInfoExtidMd5Packet::from_bytes,
+                    Self::ClientInfoExtidMd5,
+                )
+                .parse(payload)?
+                .1
+            }
+            D::ServerInfo => {
+                map(ServerInfoPacket::from_bytes, Self::ServerInfo)
+                    .parse(payload)?
+                    .1
+            }
+            D::ClientContent => {
+                map(ClientContentIdsPacket::from_bytes, Self::ClientContent)
+                    .parse(payload)?
+                    .1
+            }
+            D::ServerContent => {
+                map(ServerContentPacket::from_bytes, Self::ServerContent)
+                    .parse(payload)?
+                    .1
+            }
+        };
+
+        Ok((input, packet))
+    }
+
+    pub fn to_bytes(&self) -> std::io::Result<Vec<u8>> {
+        let buf = &mut vec![];
+        buf.push(ContentPacketDiscriminants::from(self).into());
+
+        match self {
+            ContentPacket::ClientInfoList(data) => data.write_pkt(buf)?,
+            ContentPacket::ClientInfoId(data) => data.write_pkt(buf)?,
+            ContentPacket::ClientInfoExtid(data) => data.write_pkt(buf)?,
+            ContentPacket::ClientInfoExtidMd5(data) => data.write_pkt(buf)?,
+            ContentPacket::ServerInfo(data) => data.write_pkt(buf)?,
+            ContentPacket::ClientContent(data) => data.write_pkt(buf)?,
+            ContentPacket::ServerContent(data) => data.write_pkt(buf)?,
+        }
+
+        let mut out = vec![];
+        out.write_u16::<LittleEndian>(buf.len() as u16 + 2)?;
+        out.append(buf);
+
+        Ok(out)
+    }
+}
+
+/// `CLIENT_INFO_LIST`: list all content of a type available for a game version
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClientInfoListPacket {
+    pub content_type: ContentType,
+    /// `0xffffffff` to have the server filter by `branches` instead
+    pub openttd_version: u32,
+    /// Branch name and content version, e.g. ("vanilla", "13")
+    pub branches: Vec<(CString, CString)>,
+}
+
+impl ByteWriter for ClientInfoListPacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.write_u8(self.content_type.into())?;
+        buf.write_u32::<LittleEndian>(self.openttd_version)?;
+        buf.write_u8(self.branches.len().try_into().map_err(|_| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, "too many branches")
+        })?)?;
+        for (branch, version) in &self.branches {
+            buf.extend_from_slice(branch.to_bytes_with_nul());
+            buf.extend_from_slice(version.to_bytes_with_nul());
+        }
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for ClientInfoListPacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(
+            tuple((
+                content_type,
+                le_u32,
+                length_count(le_u8, tuple((read_cstring, read_cstring))),
+            )),
+            |(content_type, openttd_version, branches)| Self {
+                content_type,
+                openttd_version,
+                branches,
+            },
+        )
+        .parse(input)
+    }
+}
+
+/// `CLIENT_INFO_ID` and `CLIENT_CONTENT`: query or download content by server-side ID
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClientContentIdsPacket {
+    pub content_ids: Vec<u32>,
+}
+
+impl ByteWriter for ClientContentIdsPacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.write_u16::<LittleEndian>(self.content_ids.len().try_into().map_err(|_| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, "too many content IDs")
+        })?)?;
+        for &id in &self.content_ids {
+            buf.write_u32::<LittleEndian>(id)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for ClientContentIdsPacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(length_count(le_u16, le_u32), |content_ids| Self {
+            content_ids,
+        })
+        .parse(input)
+    }
+}
+
+/// `CLIENT_INFO_EXTID`: query content by type and unique ID, e.g. GRF ID
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClientInfoExtidPacket {
+    pub content: Vec<(ContentType, u32)>,
+}
+
+impl ByteWriter for ClientInfoExtidPacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.write_u8(self.content.len().try_into().map_err(|_| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, "too many content entries")
+        })?)?;
+        for &(content_type, unique_id) in &self.content {
+            buf.write_u8(content_type.into())?;
+            buf.write_u32::<LittleEndian>(unique_id)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for ClientInfoExtidPacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(
+            length_count(le_u8, tuple((content_type, le_u32))),
+            |content| Self { content },
+        )
+        .parse(input)
+    }
+}
+
+/// `CLIENT_INFO_EXTID_MD5`: query content by type, unique ID and MD5
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClientInfoExtidMd5Packet {
+    pub content: Vec<(ContentType, u32, NewGRFHash)>,
+}
+
+impl ClientInfoExtidMd5Packet {
+    /// Query for NewGRFs by GRF ID and MD5, as listed by a game server
+    pub fn newgrfs<I: IntoIterator<Item = (u32, NewGRFHash)>>(newgrfs: I) -> Self {
+        Self {
+            content: newgrfs
+                .into_iter()
+                .map(|(grfid, hash)| (ContentType::NewGrf, grfid, hash))
+                .collect(),
+        }
+    }
+}
+
+impl ByteWriter for ClientInfoExtidMd5Packet {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.write_u8(self.content.len().try_into().map_err(|_| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, "too many content entries")
+        })?)?;
+        for &(content_type, unique_id, hash) in &self.content {
+            buf.write_u8(content_type.into())?;
+            buf.write_u32::<LittleEndian>(unique_id)?;
+            buf.extend_from_slice(&hash.0);
+        }
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for ClientInfoExtidMd5Packet {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(
+            length_count(le_u8, tuple((content_type, le_u32, newgrf_md5))),
+            |content| Self { content },
+        )
+        .parse(input)
+    }
+}
+
+/// `SERVER_INFO`: description of one content item
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerInfoPacket {
+    pub content_type: ContentType,
+    pub content_id: u32,
+    pub filesize: u32,
+    pub name: CString,
+    pub version: CString,
+    pub url: CString,
+    pub description: CString,
+    /// GRF ID for NewGRFs, short name for scripts
+    pub unique_id: u32,
+    pub md5: NewGRFHash,
+    pub dependencies: Vec<u32>,
+    pub tags: Vec<CString>,
+}
+
+impl ByteWriter for ServerInfoPacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.write_u8(self.content_type.into())?;
+        buf.write_u32::<LittleEndian>(self.content_id)?;
+        buf.write_u32::<LittleEndian>(self.filesize)?;
+        buf.extend_from_slice(self.name.to_bytes_with_nul());
+        buf.extend_from_slice(self.version.to_bytes_with_nul());
+        buf.extend_from_slice(self.url.to_bytes_with_nul());
+        buf.extend_from_slice(self.description.to_bytes_with_nul());
+        buf.write_u32::<LittleEndian>(self.unique_id)?;
+        buf.extend_from_slice(&self.md5.0);
+        buf.write_u8(self.dependencies.len().try_into().map_err(|_| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, "too many dependencies")
+        })?)?;
+        for &id in &self.dependencies {
+            buf.write_u32::<LittleEndian>(id)?;
+        }
+        buf.write_u8(
+            self.tags.len().try_into().map_err(|_| {
+                std::io::Error::new(std::io::ErrorKind::InvalidData, "too many tags")
+            })?,
+        )?;
+        for tag in &self.tags {
+            buf.extend_from_slice(tag.to_bytes_with_nul());
+        }
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for ServerInfoPacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        let (input, (content_type, content_id, filesize, name, version, url, description)) =
+            tuple((
+                content_type,
+                le_u32,
+                le_u32,
+                read_cstring,
+                read_cstring,
+                read_cstring,
+                read_cstring,
+            ))
+            .parse(input)?;
+        let (input, (unique_id, md5, dependencies, tags)) = tuple((
+            le_u32,
+            newgrf_md5,
+            length_count(le_u8, le_u32),
+            length_count(le_u8, read_cstring),
+        ))
+        .parse(input)?;
+
+        Ok((
+            input,
+            Self {
+                content_type,
+                content_id,
+                filesize,
+                name,
+                version,
+                url,
+                description,
+                unique_id,
+                md5,
+                dependencies,
+                tags,
+            },
+        ))
+    }
+}
+
+/// `SERVER_CONTENT`: part of a download
+///
+/// Each download is a [`ServerContentHeader`], then raw chunks of the file,
+/// then an empty packet. Which is which depends on the position in the stream,
+/// so the payload is kept as is.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerContentPacket {
+    pub data: Vec<u8>,
+}
+
+impl ServerContentPacket {
+    pub fn header(header: &ServerContentHeader) -> std::io::Result<Self> {
+        let mut data = vec![];
+        header.write_pkt(&mut data)?;
+
+        Ok(Self { data })
+    }
+}
+
+impl ByteWriter for ServerContentPacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.extend_from_slice(&self.data);
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for ServerContentPacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(rest, |data: &[u8]| Self {
+            data: data.to_vec(),
+        })
+        .parse(input)
+    }
+}
+
+/// First `SERVER_CONTENT` packet of a download
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerContentHeader {
+    pub content_type: ContentType,
+    pub content_id: u32,
+    pub filesize: u32,
+    pub filename: CString,
+}
+
+impl ByteWriter for ServerContentHeader {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.write_u8(self.content_type.into())?;
+        buf.write_u32::<LittleEndian>(self.content_id)?;
+        buf.write_u32::<LittleEndian>(self.filesize)?;
+        buf.extend_from_slice(self.filename.to_bytes_with_nul());
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for ServerContentHeader {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(
+            tuple((content_type, le_u32, le_u32, read_cstring)),
+            |(content_type, content_id, filesize, filename)| Self {
+                content_type,
+                content_id,
+                filesize,
+                filename,
+            },
+        )
+        .parse(input)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex_literal::hex;
+
+    fn fixtures() -> Vec<(Vec<u8>, ContentPacket)> {
+        vec![
+            (
+                hex!("1400 00 02 ffffffff 01 76616e696c6c6100 313300").into(),
+                ContentPacket::ClientInfoList(ClientInfoListPacket {
+                    content_type: ContentType::NewGrf,
+                    openttd_version: u32::MAX,
+                    branches: vec![(
+                        CString::new("vanilla").unwrap(),
+                        CString::new("13").unwrap(),
+                    )],
+                }),
+            ),
+            (
+                hex!("0d00 05 0200 0a000000 0b000000").into(),
+                ContentPacket::ClientContent(ClientContentIdsPacket {
+                    content_ids: vec![10, 11],
+                }),
+            ),
+            (
+                hex!("1900 03 01 02 4d470301 00112233445566778899aabbccddeeff").into(),
+                ContentPacket::ClientInfoExtidMd5(ClientInfoExtidMd5Packet::newgrfs([(
+                    0x0103474d,
+                    NewGRFHash(hex!("00112233445566778899aabbccddeeff")),
+                )])),
+            ),
+            (
+                hex!(
+                    "3b00 04 02 0a000000 00100000 4f70656e47465800 372e3100 00 00"
+                    "4d470301 00112233445566778899aabbccddeeff"
+                    "01 0b000000 01 747261696e7300"
+                )
+                .into(),
+                ContentPacket::ServerInfo(ServerInfoPacket {
+                    content_type: ContentType::NewGrf,
+                    content_id: 10,
+                    filesize: 4096,
+                    name: CString::new("OpenGFX").unwrap(),
+                    version: CString::new("7.1").unwrap(),
+                    url: CString::default(),
+                    description: CString::default(),
+                    unique_id: 0x0103474d,
+                    md5: NewGRFHash(hex!("00112233445566778899aabbccddeeff")),
+                    dependencies: vec![11],
+                    tags: vec![CString::new("trains").unwrap()],
+                }),
+            ),
+            (
+                hex!("0600 06 010203").into(),
+                ContentPacket::ServerContent(ServerContentPacket {
+                    data: vec![1, 2, 3],
+                }),
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_parse_content_packet() {
+        for (input, expectation) in fixtures() {
+            let result = ContentPacket::from_bytes(&input).unwrap();
+
+            assert_eq!(expectation, result.1);
+        }
+    }
+
+    #[test]
+    fn test_write_content_packet() {
+        for (expectation, input) in fixtures() {
+            let result = input.to_bytes().unwrap();
+
+            assert_eq!(expectation, result);
+        }
+    }
+
+    #[test]
+    fn test_write_too_many_entries() {
+        let packet = ContentPacket::ClientInfoExtid(ClientInfoExtidPacket {
+            content: vec![(ContentType::NewGrf, 0); 256],
+        });
+        let error = packet.to_bytes().unwrap_err();
+
+        assert_eq!(std::io::ErrorKind::InvalidData, error.kind());
+    }
+}
@@ -0,0 +1,297 @@
+use crate::{content::*, newgrf::NewGRFHash, newgrf_file::tar_newgrfs, util::PacketPayload};
+use anyhow::{ensure, format_err};
+use flate2::read::GzDecoder;
+use md5::{Digest, Md5};
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+/// Package written by [`ContentDownload`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompletedDownload {
+    pub header: ServerContentHeader,
+    pub path: PathBuf,
+    /// MD5 of the `.tar.gz` as received
+    pub md5: NewGRFHash,
+}
+
+#[derive(Debug)]
+struct PartialDownload {
+    header: ServerContentHeader,
+    part_path: PathBuf,
+    path: PathBuf,
+    file: File,
+    hasher: Md5,
+    received: u64,
+}
+
+/// Sink for `SERVER_CONTENT` packets that writes each package into a directory
+///
+/// Packages are written to a hidden `.part` file first and renamed into place
+/// once their size and MD5 have been checked, so the target directory never
+/// contains truncated downloads.
+#[derive(Debug)]
+pub struct ContentDownload {
+    target_dir: PathBuf,
+    expected: BTreeMap<u32, NewGRFHash>,
+    current: Option<PartialDownload>,
+    /// Rest of a failed package, dropped up to its final, empty packet
+    skipping: bool,
+}
+
+impl ContentDownload {
+    pub fn new<P: Into<PathBuf>>(target_dir: P) -> Self {
+        Self {
+            target_dir: target_dir.into(),
+            expected: BTreeMap::new(),
+            current: None,
+            skipping: false,
+        }
+    }
+
+    /// Require the next package of `content_id` to contain a NewGRF with this MD5
+    ///
+    /// The content server lists the MD5 of the `.grf` inside the package, not
+    /// of the package itself, so the package is unpacked to check it.
+    pub fn expect(&mut self, content_id: u32, md5: NewGRFHash) {
+        self.expected.insert(content_id, md5);
+    }
+
+    /// Process the next `SERVER_CONTENT` packet
+    ///
+    /// Returns the written package after its final, empty packet. On error the
+    /// partial file is removed; when that happens before the final packet, the
+    /// rest of the package is dropped and the next header starts a new one.
+    pub fn push(
+        &mut self,
+        packet: &ServerContentPacket,
+    ) -> anyhow::Result<Option<CompletedDownload>> {
+        if self.skipping {
+            self.skipping = !packet.data.is_empty();
+            return Ok(None);
+        }
+
+        let result = self.process(&packet.data);
+        if result.is_err() {
+            if let Some(current) = self.current.take() {
+                let _ = fs::remove_file(current.part_path);
+            }
+            self.skipping = !packet.data.is_empty();
+        }
+
+        result
+    }
+
+    fn process(&mut self, data: &[u8]) -> anyhow::Result<Option<CompletedDownload>> {
+        let current = match &mut self.current {
+            None => {
+                let (_, header) = ServerContentHeader::from_bytes(data)
+                    .map_err(|e| format_err!("malformed content header: {:?}", e))?;
+                self.current = Some(self.begin(header)?);
+                return Ok(None);
+            }
+            Some(current) => current,
+        };
+
+        if !data.is_empty() {
+            current.received += data.len() as u64;
+            ensure!(
+                current.received <= u64::from(current.header.filesize),
+                "content {} is larger than announced {} bytes",
+                current.header.content_id,
+                current.header.filesize
+            );
+            current.file.write_all(data)?;
+            current.hasher.update(data);
+            return Ok(None);
+        }
+
+        let current = self.current.take().unwrap();
+        let content_id = current.header.content_id;
+        let expected = self.expected.remove(&content_id);
+        ensure!(
+            current.received == u64::from(current.header.filesize),
+            "content {} is {} bytes, announced {}",
+            content_id,
+            current.received,
+            current.header.filesize
+        );
+        let md5 = NewGRFHash(current.hasher.finalize().into());
+
+        current.file.sync_all()?;
+        drop(current.file);
+        if let Some(expected) = expected {
+            if let Err(e) = verify_package(&current.part_path, content_id, &expected) {
+                let _ = fs::remove_file(&current.part_path);
+                return Err(e);
+            }
+        }
+        fs::rename(&current.part_path, &current.path)?;
+
+        Ok(Some(CompletedDownload {
+            header: current.header,
+            path: current.path,
+            md5,
+        }))
+    }
+
+    fn begin(&self, header: ServerContentHeader) -> anyhow::Result<PartialDownload> {
+        let filename = header.filename.to_str()?;
+        ensure!(
+            is_plain_filename(filename),
+            "refusing content file name {:?}",
+            filename
+        );
+
+        let path = self.target_dir.join(format!("{}.tar.gz", filename));
+        let part_path = self.target_dir.join(format!(".{}.tar.gz.part", filename));
+        let file = File::create(&part_path)?;
+
+        Ok(PartialDownload {
+            header,
+            part_path,
+            path,
+            file,
+            hasher: Md5::new(),
+            received: 0,
+        })
+    }
+}
+
+/// Check that a `.tar.gz` package contains a NewGRF with the expected MD5
+fn verify_package(path: &Path, content_id: u32, expected: &NewGRFHash) -> anyhow::Result<()> {
+    let newgrfs = tar_newgrfs(GzDecoder::new(BufReader::new(File::open(path)?)))?;
+    ensure!(
+        newgrfs.iter().any(|(_, (_, md5))| md5 == expected),
+        "content {} contains no NewGRF with MD5 {}",
+        content_id,
+        expected
+    );
+
+    Ok(())
+}
+
+/// File names come from the server and must not escape the target directory
+fn is_plain_filename(filename: &str) -> bool {
+    !filename.is_empty()
+        && !filename.starts_with('.')
+        && Path::new(filename).file_name() == Some(filename.as_ref())
+        && !filename.contains(['/', '\\'])
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::newgrf_file::{grf_md5, tests::grf_v2};
+    use flate2::{write::GzEncoder, Compression};
+    use std::ffi::CString;
+
+    fn header(filesize: u32, filename: &str) -> ServerContentPacket {
+        ServerContentPacket::header(&ServerContentHeader {
+            content_type: ContentType::NewGrf,
+            content_id: 10,
+            filesize,
+            filename: CString::new(filename).unwrap(),
+        })
+        .unwrap()
+    }
+
+    fn chunk(data: &[u8]) -> ServerContentPacket {
+        ServerContentPacket {
+            data: data.to_vec(),
+        }
+    }
+
+    /// `.tar.gz` package of a single NewGRF, as served by the content server
+    pub(crate) fn package(grf: &[u8]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(grf.len() as u64);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "opengfx/ogfx.grf", grf)
+            .unwrap();
+
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(&builder.into_inner().unwrap()).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_content_download() {
+        let dir = std::env::temp_dir().join(format!("openttd-content-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let grf = grf_v2(0x0103474d, b"sprites");
+        let package = package(&grf);
+        let size = package.len() as u32;
+        let (first, second) = package.split_at(package.len() / 2);
+
+        let mut download = ContentDownload::new(&dir);
+        download.expect(10, grf_md5(&grf));
+        assert_eq!(None, download.push(&header(size, "opengfx")).unwrap());
+        assert_eq!(None, download.push(&chunk(first)).unwrap());
+        assert_eq!(None, download.push(&chunk(second)).unwrap());
+        let result = download.push(&chunk(b"")).unwrap().unwrap();
+
+        assert_eq!(dir.join("opengfx.tar.gz"), result.path);
+        assert_eq!(NewGRFHash(Md5::digest(&package).into()), result.md5);
+        assert_eq!(package, fs::read(&result.path).unwrap());
+        assert!(download.expected.is_empty());
+
+        // A package without the expected NewGRF is rejected and leaves nothing behind
+        download.expect(10, NewGRFHash([0; 16]));
+        download.push(&header(size, "broken")).unwrap();
+        download.push(&chunk(&package)).unwrap();
+        assert!(download.push(&chunk(b"")).is_err());
+        assert!(!dir.join("broken.tar.gz").exists());
+        assert!(!dir.join(".broken.tar.gz.part").exists());
+
+        // So is one that is not a package at all
+        download.expect(10, grf_md5(&grf));
+        download.push(&header(5, "garbage")).unwrap();
+        download.push(&chunk(b"hello")).unwrap();
+        assert!(download.push(&chunk(b"")).is_err());
+        assert!(!dir.join("garbage.tar.gz").exists());
+
+        assert!(download.push(&header(1, "../escape")).is_err());
+        assert_eq!(None, download.push(&chunk(b"x")).unwrap());
+        assert_eq!(None, download.push(&chunk(b"")).unwrap());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_content_download_after_oversize_package() {
+        let dir = std::env::temp_dir().join(format!("openttd-oversize-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let grf = grf_v2(0x0103474d, b"sprites");
+        let package = package(&grf);
+        let (first, second) = package.split_at(package.len() / 2);
+
+        let mut download = ContentDownload::new(&dir);
+        download
+            .push(&header(first.len() as u32, "oversize"))
+            .unwrap();
+        download.push(&chunk(first)).unwrap();
+        assert!(download.push(&chunk(second)).is_err());
+        assert!(!dir.join(".oversize.tar.gz.part").exists());
+
+        // The rest of the failed package is dropped, not taken for a header
+        assert_eq!(None, download.push(&chunk(second)).unwrap());
+        assert_eq!(None, download.push(&chunk(b"")).unwrap());
+
+        download.expect(10, grf_md5(&grf));
+        download
+            .push(&header(package.len() as u32, "opengfx"))
+            .unwrap();
+        download.push(&chunk(&package)).unwrap();
+        let result = download.push(&chunk(b"")).unwrap().unwrap();
+        assert_eq!(package, fs::read(&result.path).unwrap());
+        assert!(!dir.join("oversize.tar.gz").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,302 @@
+use crate::{content::*, content_download::*};
+use anyhow::{ensure, format_err};
+use std::{
+    ffi::{CString, NulError},
+    fmt::Write,
+};
+
+/// Line of the HTTP content server's answer: where to download one item
+#[derive(Clone, Debug, PartialEq)]
+pub struct HttpContentEntry {
+    pub content_id: u32,
+    pub content_type: ContentType,
+    pub filesize: u32,
+    /// File name without the `.tar.gz` extension
+    pub filename: String,
+    pub url: String,
+}
+
+impl HttpContentEntry {
+    /// Same header the TCP content server sends in its first `SERVER_CONTENT` packet
+    pub fn header(&self) -> Result<ServerContentHeader, NulError> {
+        Ok(ServerContentHeader {
+            content_type: self.content_type,
+            content_id: self.content_id,
+            filesize: self.filesize,
+            filename: CString::new(self.filename.as_str())?,
+        })
+    }
+}
+
+/// Body of the POST request for the download URLs of `content_ids`
+pub fn encode_http_content_request(content_ids: &[u32]) -> String {
+    content_ids.iter().fold(String::new(), |mut out, id| {
+        let _ = writeln!(out, "{}", id);
+        out
+    })
+}
+
+/// Content IDs from the body of a POST request, one per line
+pub fn parse_http_content_request(body: &str) -> anyhow::Result<Vec<u32>> {
+    body.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| Ok(line.trim().parse()?))
+        .collect()
+}
+
+/// Answer with one `content_id,type,filesize,filename,url` line per item
+pub fn encode_http_content_list(entries: &[HttpContentEntry]) -> String {
+    entries.iter().fold(String::new(), |mut out, entry| {
+        let _ = writeln!(
+            out,
+            "{},{},{},{},{}",
+            entry.content_id,
+            u8::from(entry.content_type),
+            entry.filesize,
+            entry.filename,
+            entry.url
+        );
+        out
+    })
+}
+
+/// Download URLs from the answer to a POST request; blank lines are skipped
+pub fn parse_http_content_list(body: &str) -> anyhow::Result<Vec<HttpContentEntry>> {
+    body.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let mut fields = line.splitn(5, ',');
+            let mut next = |name| {
+                fields
+                    .next()
+                    .ok_or_else(|| format_err!("missing {} in content list line {:?}", name, line))
+            };
+            let content_id = next("content ID")?.parse()?;
+            let content_type = ContentType::try_from(next("type")?.parse::<u8>()?)?;
+            let filesize = next("file size")?.parse()?;
+            let filename = next("file name")?.to_string();
+            let url = next("URL")?.to_string();
+            ensure!(!url.is_empty(), "empty URL in content list line {:?}", line);
+
+            Ok(HttpContentEntry {
+                content_id,
+                content_type,
+                filesize,
+                filename,
+                url,
+            })
+        })
+        .collect()
+}
+
+impl ContentDownload {
+    /// Write a package downloaded from `entry.url`, with the same checks as over TCP
+    pub fn push_http(
+        &mut self,
+        entry: &HttpContentEntry,
+        body: &[u8],
+    ) -> anyhow::Result<CompletedDownload> {
+        let packets = [
+            ServerContentPacket::header(&entry.header()?)?,
+            ServerContentPacket {
+                data: body.to_vec(),
+            },
+            ServerContentPacket { data: vec![] },
+        ];
+
+        let mut completed = None;
+        for packet in &packets {
+            completed = self.push(packet)?;
+        }
+
+        completed.ok_or_else(|| format_err!("content {} was not completed", entry.content_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_http_content_list() {
+        let body = "10,2,4096,4d470301-OpenGFX-7.1,https://example.com/a,b.tar.gz\n\
+                    \n\
+                    11,9,12,474d4f44-Script,https://example.com/c.tar.gz\r\n";
+        let expectation = vec![
+            HttpContentEntry {
+                content_id: 10,
+                content_type: ContentType::NewGrf,
+                filesize: 4096,
+                filename: "4d470301-OpenGFX-7.1".into(),
+                url: "https://example.com/a,b.tar.gz".into(),
+            },
+            HttpContentEntry {
+                content_id: 11,
+                content_type: ContentType::Game,
+                filesize: 12,
+                filename: "474d4f44-Script".into(),
+                url: "https://example.com/c.tar.gz".into(),
+            },
+        ];
+
+        let result = parse_http_content_list(body).unwrap();
+        assert_eq!(expectation, result);
+        assert_eq!(
+            expectation,
+            parse_http_content_list(&encode_http_content_list(&result)).unwrap()
+        );
+
+        assert!(parse_http_content_list("10,2,4096,name").is_err());
+        assert_eq!(
+            vec![10, 11],
+            parse_http_content_request(&encode_http_content_request(&[10, 11])).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_http_content_download() {
+        let dir = std::env::temp_dir().join(format!("openttd-content-http-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let entry = HttpContentEntry {
+            content_id: 10,
+            content_type: ContentType::NewGrf,
+            filesize: 5,
+            filename: "opengfx".into(),
+            url: "http://127.0.0.1/opengfx.tar.gz".into(),
+        };
+
+        let mut download = ContentDownload::new(&dir);
+        let result = download.push_http(&entry, b"hello").unwrap();
+        assert_eq!(dir.join("opengfx.tar.gz"), result.path);
+        assert!(download.push_http(&entry, b"hello world").is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Head and body of an HTTP/1.0 message with a `Content-Length`
+    #[cfg(feature = "tokio")]
+    async fn read_http_message(stream: &mut tokio::net::TcpStream) -> (String, Vec<u8>) {
+        use tokio::io::AsyncReadExt;
+
+        let mut data = vec![];
+        let head_len = loop {
+            if let Some(pos) = data.windows(4).position(|w| w == b"\r\n\r\n") {
+                break pos + 4;
+            }
+            let mut buf = [0; 1024];
+            let n = stream.read(&mut buf).await.unwrap();
+            assert!(n > 0, "connection closed within the HTTP head");
+            data.extend_from_slice(&buf[..n]);
+        };
+
+        let head = String::from_utf8(data[..head_len].to_vec()).unwrap();
+        let content_length = head
+            .lines()
+            .find_map(|line| line.strip_prefix("Content-Length: "))
+            .map_or(0, |v| v.parse().unwrap());
+        let mut body = data[head_len..].to_vec();
+        body.resize(content_length, 0);
+        stream
+            .read_exact(&mut body[data.len() - head_len..])
+            .await
+            .unwrap();
+
+        (head, body)
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_http_content_server() {
+        use crate::{
+            content_download::tests::package,
+            newgrf_file::{grf_md5, tests::grf_v2},
+        };
+        use tokio::{
+            io::AsyncWriteExt,
+            net::{TcpListener, TcpStream},
+        };
+
+        let dir = std::env::temp_dir().join(format!(
+            "openttd-content-http-server-{}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let grf = grf_v2(0x0103474d, b"sprites");
+        let package = package(&grf);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let server = {
+            let (base_url, package) = (base_url.clone(), package.clone());
+            tokio::spawn(async move {
+                let mut requests = vec![];
+                for _ in 0..2 {
+                    let (mut stream, _) = listener.accept().await.unwrap();
+                    let (head, body) = read_http_message(&mut stream).await;
+                    let body = match head.split_whitespace().take(2).collect::<Vec<_>>()[..] {
+                        ["POST", "/"] => {
+                            let ids =
+                                parse_http_content_request(std::str::from_utf8(&body).unwrap())
+                                    .unwrap();
+                            let entries = ids
+                                .iter()
+                                .map(|&content_id| HttpContentEntry {
+                                    content_id,
+                                    content_type: ContentType::NewGrf,
+                                    filesize: package.len() as u32,
+                                    filename: format!("content-{}", content_id),
+                                    url: format!("{}/content-{}.tar.gz", base_url, content_id),
+                                })
+                                .collect::<Vec<_>>();
+                            encode_http_content_list(&entries).into_bytes()
+                        }
+                        ["GET", "/content-10.tar.gz"] => package.clone(),
+                        _ => panic!("unexpected request {:?}", head),
+                    };
+                    requests.push(head);
+
+                    let head = format!("HTTP/1.0 200 OK\r\nContent-Length: {}\r\n\r\n", body.len());
+                    stream.write_all(head.as_bytes()).await.unwrap();
+                    stream.write_all(&body).await.unwrap();
+                }
+                requests
+            })
+        };
+
+        let addr = base_url.strip_prefix("http://").unwrap();
+        let request = encode_http_content_request(&[10]);
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(
+                format!(
+                    "POST / HTTP/1.0\r\nContent-Type: text/plain\r\nContent-Length: {}\r\n\r\n{}",
+                    request.len(),
+                    request
+                )
+                .as_bytes(),
+            )
+            .await
+            .unwrap();
+        let (head, body) = read_http_message(&mut stream).await;
+        assert!(head.starts_with("HTTP/1.0 200"));
+        let entries = parse_http_content_list(std::str::from_utf8(&body).unwrap()).unwrap();
+        assert_eq!(1, entries.len());
+
+        let path = entries[0].url.strip_prefix(&base_url).unwrap();
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(format!("GET {} HTTP/1.0\r\n\r\n", path).as_bytes())
+            .await
+            .unwrap();
+        let (_, body) = read_http_message(&mut stream).await;
+
+        let mut download = ContentDownload::new(&dir);
+        download.expect(10, grf_md5(&grf));
+        let completed = download.push_http(&entries[0], &body).unwrap();
+        assert_eq!(dir.join("content-10.tar.gz"), completed.path);
+        assert_eq!(package, std::fs::read(&completed.path).unwrap());
+        assert_eq!(2, server.await.unwrap().len());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
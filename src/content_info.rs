@@ -0,0 +1,190 @@
+use crate::{content::*, newgrf::NewGRFHash};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ffi::{CString, NulError},
+};
+
+/// Content item as listed by the content server
+#[derive(Clone, Debug, PartialEq)]
+pub struct ContentInfo {
+    pub content_type: ContentType,
+    pub id: u32,
+    pub filesize: u32,
+    pub name: String,
+    pub version: String,
+    pub url: String,
+    pub description: String,
+    /// GRF ID for NewGRFs, short name for scripts
+    pub unique_id: u32,
+    pub md5: NewGRFHash,
+    /// Content IDs this item needs
+    pub dependencies: Vec<u32>,
+    pub tags: Vec<String>,
+}
+
+impl ContentInfo {
+    /// Whether this is the NewGRF a server lists with `grfid` and `md5`
+    pub fn is_newgrf(&self, grfid: u32, md5: &NewGRFHash) -> bool {
+        self.content_type == ContentType::NewGrf && self.unique_id == grfid && self.md5 == *md5
+    }
+
+    pub fn to_packet(&self) -> Result<ServerInfoPacket, NulError> {
+        Ok(ServerInfoPacket {
+            content_type: self.content_type,
+            content_id: self.id,
+            filesize: self.filesize,
+            name: CString::new(self.name.as_str())?,
+            version: CString::new(self.version.as_str())?,
+            url: CString::new(self.url.as_str())?,
+            description: CString::new(self.description.as_str())?,
+            unique_id: self.unique_id,
+            md5: self.md5,
+            dependencies: self.dependencies.clone(),
+            tags: self
+                .tags
+                .iter()
+                .map(|tag| CString::new(tag.as_str()))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+impl From<ServerInfoPacket> for ContentInfo {
+    fn from(packet: ServerInfoPacket) -> Self {
+        Self {
+            content_type: packet.content_type,
+            id: packet.content_id,
+            filesize: packet.filesize,
+            name: packet.name.to_string_lossy().into_owned(),
+            version: packet.version.to_string_lossy().into_owned(),
+            url: packet.url.to_string_lossy().into_owned(),
+            description: packet.description.to_string_lossy().into_owned(),
+            unique_id: packet.unique_id,
+            md5: packet.md5,
+            dependencies: packet.dependencies,
+            tags: packet
+                .tags
+                .iter()
+                .map(|tag| tag.to_string_lossy().into_owned())
+                .collect(),
+        }
+    }
+}
+
+/// Outcome of [`ContentCatalog::resolve`]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ContentResolution {
+    /// Content IDs to download, every item after its dependencies
+    pub download: Vec<u32>,
+    /// IDs whose info is not known yet; query them with `CLIENT_INFO_ID` and resolve again
+    pub missing: BTreeSet<u32>,
+}
+
+/// Content info received so far, keyed by content ID
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ContentCatalog {
+    items: BTreeMap<u32, ContentInfo>,
+}
+
+impl ContentCatalog {
+    pub fn insert(&mut self, info: ContentInfo) {
+        self.items.insert(info.id, info);
+    }
+
+    pub fn get(&self, id: u32) -> Option<&ContentInfo> {
+        self.items.get(&id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ContentInfo> {
+        self.items.values()
+    }
+
+    /// Find the content item of a NewGRF listed by a server
+    pub fn find_newgrf(&self, grfid: u32, md5: &NewGRFHash) -> Option<&ContentInfo> {
+        self.items.values().find(|info| info.is_newgrf(grfid, md5))
+    }
+
+    /// Compute the full download set for `wanted`, including all dependencies
+    pub fn resolve<I: IntoIterator<Item = u32>>(&self, wanted: I) -> ContentResolution {
+        let mut resolution = ContentResolution::default();
+        let mut visited = BTreeSet::new();
+        for id in wanted {
+            self.visit(id, &mut visited, &mut resolution);
+        }
+
+        resolution
+    }
+
+    fn visit(&self, id: u32, visited: &mut BTreeSet<u32>, resolution: &mut ContentResolution) {
+        if !visited.insert(id) {
+            return;
+        }
+
+        match self.items.get(&id) {
+            Some(info) => {
+                for &dependency in &info.dependencies {
+                    self.visit(dependency, visited, resolution);
+                }
+                resolution.download.push(id);
+            }
+            None => {
+                resolution.missing.insert(id);
+            }
+        }
+    }
+}
+
+impl FromIterator<ContentInfo> for ContentCatalog {
+    fn from_iter<I: IntoIterator<Item = ContentInfo>>(iter: I) -> Self {
+        Self {
+            items: iter.into_iter().map(|info| (info.id, info)).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(id: u32, dependencies: Vec<u32>) -> ContentInfo {
+        ContentInfo {
+            content_type: ContentType::NewGrf,
+            id,
+            filesize: 1024,
+            name: format!("Set {}", id),
+            version: "1.0".into(),
+            url: String::new(),
+            description: String::new(),
+            unique_id: id,
+            md5: NewGRFHash([id as u8; 16]),
+            dependencies,
+            tags: vec![],
+        }
+    }
+
+    #[test]
+    fn test_resolve_dependencies() {
+        let catalog = [
+            info(1, vec![2, 3]),
+            info(2, vec![3]),
+            info(3, vec![]),
+            info(4, vec![5]),
+            // Cyclic dependencies must not hang the resolver
+            info(6, vec![7]),
+            info(7, vec![6]),
+        ]
+        .into_iter()
+        .collect::<ContentCatalog>();
+
+        let result = catalog.resolve([1, 4, 6]);
+
+        assert_eq!(vec![3, 2, 1, 4, 7, 6], result.download);
+        assert_eq!(BTreeSet::from([5]), result.missing);
+        assert_eq!(
+            Some(2),
+            catalog
+                .find_newgrf(2, &NewGRFHash([2; 16]))
+                .map(|info| info.id)
+        );
+    }
+}
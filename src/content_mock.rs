@@ -0,0 +1,288 @@
+use crate::{content::*, content_info::*, newgrf_file::*, tcp::read_frame};
+use anyhow::format_err;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use std::{
+    collections::BTreeMap,
+    ffi::CString,
+    fs,
+    io::{Read, Write},
+    net::SocketAddr,
+    path::Path,
+};
+use tokio::{
+    io::AsyncWriteExt,
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+};
+
+/// Bytes of package data per `SERVER_CONTENT` packet
+const CHUNK_SIZE: usize = 1400;
+
+#[derive(Clone, Debug)]
+struct MockContent {
+    info: ContentInfo,
+    /// Package as sent to clients, always gzipped
+    package: Vec<u8>,
+}
+
+/// Content server stand-in serving the NewGRF packages of a local directory
+///
+/// Every `.tar` or `.tar.gz` containing a `.grf` becomes a content item
+/// identified by that NewGRF's GRF ID and MD5, so game clients and
+/// [`ContentDownload`](crate::ContentDownload) find the NewGRFs a game server
+/// lists. Content IDs are assigned in file name order, starting at 1.
+#[derive(Debug)]
+pub struct MockContentServer {
+    listener: TcpListener,
+    items: BTreeMap<u32, MockContent>,
+}
+
+impl MockContentServer {
+    pub async fn bind<A: ToSocketAddrs, P: AsRef<Path>>(addr: A, dir: P) -> anyhow::Result<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(addr).await?,
+            items: index_packages(dir.as_ref())?,
+        })
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Content items found in the directory
+    pub fn catalog(&self) -> ContentCatalog {
+        self.items.values().map(|item| item.info.clone()).collect()
+    }
+
+    /// Serve clients one after another until accepting fails
+    pub async fn run(&self) -> anyhow::Result<()> {
+        loop {
+            self.serve().await?;
+        }
+    }
+
+    /// Serve one client connection until it disconnects
+    pub async fn serve(&self) -> anyhow::Result<()> {
+        let (mut stream, _) = self.listener.accept().await?;
+
+        loop {
+            let frame = match read_frame(&mut stream).await {
+                Ok(frame) => frame,
+                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+                Err(e) => return Err(e.into()),
+            };
+            let (_, packet) = ContentPacket::from_bytes(&frame)
+                .map_err(|e| format_err!("malformed packet: {:?}", e))?;
+
+            match packet {
+                ContentPacket::ClientInfoList(data) => {
+                    let ids = self
+                        .items
+                        .iter()
+                        .filter(|(_, item)| item.info.content_type == data.content_type)
+                        .map(|(&id, _)| id)
+                        .collect::<Vec<_>>();
+                    self.send_info(&mut stream, ids).await?;
+                }
+                ContentPacket::ClientInfoId(data) => {
+                    self.send_info(&mut stream, data.content_ids).await?
+                }
+                ContentPacket::ClientInfoExtid(data) => {
+                    let ids = self
+                        .find(|info| data.content.contains(&(info.content_type, info.unique_id)));
+                    self.send_info(&mut stream, ids).await?;
+                }
+                ContentPacket::ClientInfoExtidMd5(data) => {
+                    let ids = self.find(|info| {
+                        data.content
+                            .contains(&(info.content_type, info.unique_id, info.md5))
+                    });
+                    self.send_info(&mut stream, ids).await?;
+                }
+                ContentPacket::ClientContent(data) => {
+                    for id in data.content_ids {
+                        if let Some(item) = self.items.get(&id) {
+                            send_package(&mut stream, item).await?;
+                        }
+                    }
+                }
+                ContentPacket::ServerInfo(_) | ContentPacket::ServerContent(_) => {}
+            }
+        }
+    }
+
+    fn find<F: Fn(&ContentInfo) -> bool>(&self, f: F) -> Vec<u32> {
+        self.items
+            .iter()
+            .filter(|(_, item)| f(&item.info))
+            .map(|(&id, _)| id)
+            .collect()
+    }
+
+    async fn send_info(&self, stream: &mut TcpStream, ids: Vec<u32>) -> anyhow::Result<()> {
+        for id in ids {
+            if let Some(item) = self.items.get(&id) {
+                let packet = ContentPacket::ServerInfo(item.info.to_packet()?);
+                stream.write_all(&packet.to_bytes()?).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+async fn send_package(stream: &mut TcpStream, item: &MockContent) -> anyhow::Result<()> {
+    let header = ServerContentPacket::header(&ServerContentHeader {
+        content_type: item.info.content_type,
+        content_id: item.info.id,
+        filesize: item.info.filesize,
+        filename: CString::new(item.info.name.as_str())?,
+    })?;
+    let chunks = item
+        .package
+        .chunks(CHUNK_SIZE)
+        .map(|chunk| ServerContentPacket {
+            data: chunk.to_vec(),
+        });
+    let end = ServerContentPacket { data: vec![] };
+
+    for packet in std::iter::once(header)
+        .chain(chunks)
+        .chain(std::iter::once(end))
+    {
+        stream
+            .write_all(&ContentPacket::ServerContent(packet).to_bytes()?)
+            .await?;
+    }
+
+    Ok(())
+}
+
+fn index_packages(dir: &Path) -> anyhow::Result<BTreeMap<u32, MockContent>> {
+    let mut paths = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    paths.sort();
+
+    let mut items = BTreeMap::new();
+    for path in paths {
+        let file_name = path
+            .file_name()
+            .and_then(|v| v.to_str())
+            .unwrap_or_default();
+        let (name, gzipped) = if let Some(name) = file_name.strip_suffix(".tar.gz") {
+            (name.to_string(), true)
+        } else if let Some(name) = file_name.strip_suffix(".tar") {
+            (name.to_string(), false)
+        } else {
+            continue;
+        };
+
+        let raw = fs::read(&path)?;
+        let tar = if gzipped {
+            let mut tar = vec![];
+            GzDecoder::new(&raw[..]).read_to_end(&mut tar)?;
+            tar
+        } else {
+            raw.clone()
+        };
+        let Some((_, (unique_id, md5))) = tar_newgrfs(&tar[..])?.into_iter().next() else {
+            continue;
+        };
+        let package = if gzipped {
+            raw
+        } else {
+            let mut encoder = GzEncoder::new(vec![], Compression::default());
+            encoder.write_all(&raw)?;
+            encoder.finish()?
+        };
+
+        let id = items.len() as u32 + 1;
+        let info = ContentInfo {
+            content_type: ContentType::NewGrf,
+            id,
+            filesize: package.len().try_into()?,
+            name,
+            version: String::new(),
+            url: String::new(),
+            description: String::new(),
+            unique_id,
+            md5,
+            dependencies: vec![],
+            tags: vec![],
+        };
+        items.insert(id, MockContent { info, package });
+    }
+
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{content_download::*, newgrf_file::tests::grf_v2};
+
+    #[tokio::test]
+    async fn test_mock_content_server() {
+        let dir = std::env::temp_dir().join(format!("openttd-content-mock-{}", std::process::id()));
+        let download_dir = dir.join("download");
+        fs::create_dir_all(&download_dir).unwrap();
+
+        let grf = grf_v2(0x0103474d, b"");
+        let mut builder = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(grf.len() as u64);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "test/test.grf", &grf[..])
+            .unwrap();
+        fs::write(dir.join("test.tar"), builder.into_inner().unwrap()).unwrap();
+
+        let server = MockContentServer::bind("127.0.0.1:0", &dir).await.unwrap();
+        let addr = server.local_addr().unwrap();
+        let md5 = grf_md5(&grf);
+        tokio::spawn(async move { server.serve().await.unwrap() });
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let query = ContentPacket::ClientInfoExtidMd5(ClientInfoExtidMd5Packet::newgrfs([(
+            0x0103474d, md5,
+        )]));
+        stream.write_all(&query.to_bytes().unwrap()).await.unwrap();
+        let frame = read_frame(&mut stream).await.unwrap();
+        let info = match ContentPacket::from_bytes(&frame).unwrap().1 {
+            ContentPacket::ServerInfo(info) => ContentInfo::from(info),
+            packet => panic!("unexpected packet {:?}", packet),
+        };
+        assert_eq!(1, info.id);
+        assert!(info.is_newgrf(0x0103474d, &md5));
+
+        let request = ContentPacket::ClientContent(ClientContentIdsPacket {
+            content_ids: vec![info.id],
+        });
+        stream
+            .write_all(&request.to_bytes().unwrap())
+            .await
+            .unwrap();
+        let mut download = ContentDownload::new(&download_dir);
+        let completed = loop {
+            let frame = read_frame(&mut stream).await.unwrap();
+            let ContentPacket::ServerContent(packet) = ContentPacket::from_bytes(&frame).unwrap().1
+            else {
+                panic!("expected SERVER_CONTENT");
+            };
+            if let Some(completed) = download.push(&packet).unwrap() {
+                break completed;
+            }
+        };
+
+        let mut tar = vec![];
+        GzDecoder::new(&fs::read(completed.path).unwrap()[..])
+            .read_to_end(&mut tar)
+            .unwrap();
+        assert_eq!(
+            vec![("test/test.grf".into(), (0x0103474d, md5))],
+            tar_newgrfs(&tar[..]).unwrap()
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,70 @@
+use crate::util::*;
+use byteorder::{LittleEndian, WriteBytesExt};
+use nom::{
+    self,
+    combinator::{map, opt},
+    number::complete::*,
+    sequence::tuple,
+    *,
+};
+
+/// `SERVER_FRAME`: how far clients may advance the game
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerFramePacket {
+    pub frame_counter_server: u32,
+    pub frame_counter_max: u32,
+    /// Sent now and then; the client has to echo it back in its next `CLIENT_ACK`
+    pub token: Option<u8>,
+}
+
+impl ByteWriter for ServerFramePacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.write_u32::<LittleEndian>(self.frame_counter_server)?;
+        buf.write_u32::<LittleEndian>(self.frame_counter_max)?;
+        if let Some(token) = self.token {
+            buf.write_u8(token)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for ServerFramePacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(
+            tuple((le_u32, le_u32, opt(le_u8))),
+            |(frame_counter_server, frame_counter_max, token)| Self {
+                frame_counter_server,
+                frame_counter_max,
+                token,
+            },
+        )
+        .parse(input)
+    }
+}
+
+/// `CLIENT_ACK`: the client has executed the game up to the given frame
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClientAckPacket {
+    pub frame_counter: u32,
+    pub token: u8,
+}
+
+impl ByteWriter for ClientAckPacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.write_u32::<LittleEndian>(self.frame_counter)?;
+        buf.write_u8(self.token)?;
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for ClientAckPacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(tuple((le_u32, le_u8)), |(frame_counter, token)| Self {
+            frame_counter,
+            token,
+        })
+        .parse(input)
+    }
+}
@@ -0,0 +1,220 @@
+use anyhow::format_err;
+use chrono::{Datelike, NaiveDate};
+use nom::{combinator::map, number::complete::le_u32, IResult, Parser};
+use std::{fmt, ops::Sub};
+
+const DAYS_IN_YEAR: u32 = 365;
+
+/// Days before each month of a leap year
+const ACCUM_DAYS_FOR_MONTH: [u32; 12] = [0, 31, 60, 91, 121, 152, 182, 213, 244, 274, 305, 335];
+
+/// Leap years as in OpenTTD: the proleptic Gregorian calendar, year 0 included
+pub fn is_leap_year(year: u32) -> bool {
+    year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400))
+}
+
+/// Days from 1 January of year 0 until 1 January of `year`
+fn days_till(year: u32) -> u64 {
+    let leap_years = match year {
+        0 => 0,
+        _ => {
+            let y = u64::from(year) - 1;
+            y / 4 - y / 100 + y / 400 + 1
+        }
+    };
+    u64::from(DAYS_IN_YEAR) * u64::from(year) + leap_years
+}
+
+fn days_in_month(year: u32, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Calendar date; `month` and `day` count from 1
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct YearMonthDay {
+    pub year: u32,
+    pub month: u8,
+    pub day: u8,
+}
+
+/// In-game date as sent over the network: days since 1 January of year 0
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct GameDate(pub u32);
+
+impl GameDate {
+    /// `None` for days that do not exist or dates out of range
+    pub fn from_ymd(year: u32, month: u8, day: u8) -> Option<Self> {
+        if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+            return None;
+        }
+
+        let mut days = ACCUM_DAYS_FOR_MONTH[usize::from(month - 1)] + u32::from(day) - 1;
+        // Skip 29 February in non-leap years
+        if !is_leap_year(year) && month > 2 {
+            days -= 1;
+        }
+
+        (days_till(year) + u64::from(days))
+            .try_into()
+            .ok()
+            .map(Self)
+    }
+
+    pub fn ymd(&self) -> YearMonthDay {
+        let date = u64::from(self.0);
+        // Estimate from the average year length, then correct by at most a year
+        let mut year = (date * 400 / 146097) as u32;
+        while days_till(year) > date {
+            year -= 1;
+        }
+        while days_till(year + 1) <= date {
+            year += 1;
+        }
+
+        let mut rem = (date - days_till(year)) as u32;
+        let mut month = 1;
+        while rem >= u32::from(days_in_month(year, month)) {
+            rem -= u32::from(days_in_month(year, month));
+            month += 1;
+        }
+
+        YearMonthDay {
+            year,
+            month,
+            day: rem as u8 + 1,
+        }
+    }
+
+    pub fn year(&self) -> u32 {
+        self.ymd().year
+    }
+
+    pub fn checked_add_days(self, days: i64) -> Option<Self> {
+        i64::from(self.0)
+            .checked_add(days)?
+            .try_into()
+            .ok()
+            .map(Self)
+    }
+
+    /// Same day `months` later, clamped to the end of shorter months
+    pub fn checked_add_months(self, months: i32) -> Option<Self> {
+        let ymd = self.ymd();
+        let month = i64::from(ymd.year) * 12 + i64::from(ymd.month - 1) + i64::from(months);
+        let year = u32::try_from(month.div_euclid(12)).ok()?;
+        let month = month.rem_euclid(12) as u8 + 1;
+        Self::from_ymd(year, month, ymd.day.min(days_in_month(year, month)))
+    }
+
+    pub fn checked_add_years(self, years: i32) -> Option<Self> {
+        self.checked_add_months(years.checked_mul(12)?)
+    }
+}
+
+/// Number of days between two dates
+impl Sub for GameDate {
+    type Output = i64;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        i64::from(self.0) - i64::from(rhs.0)
+    }
+}
+
+/// Fails past chrono's last supported year, 262143
+impl TryFrom<GameDate> for NaiveDate {
+    type Error = anyhow::Error;
+
+    fn try_from(value: GameDate) -> Result<Self, Self::Error> {
+        let ymd = value.ymd();
+        i32::try_from(ymd.year)
+            .ok()
+            .and_then(|year| NaiveDate::from_ymd_opt(year, ymd.month.into(), ymd.day.into()))
+            .ok_or_else(|| format_err!("{} is out of the NaiveDate range", value))
+    }
+}
+
+impl TryFrom<NaiveDate> for GameDate {
+    type Error = anyhow::Error;
+
+    fn try_from(value: NaiveDate) -> Result<Self, Self::Error> {
+        u32::try_from(value.year())
+            .ok()
+            .and_then(|year| Self::from_ymd(year, value.month() as u8, value.day() as u8))
+            .ok_or_else(|| format_err!("{} is out of the game date range", value))
+    }
+}
+
+/// ISO 8601, as in OpenTTD's `STR_FORMAT_DATE_ISO`
+impl fmt::Display for GameDate {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        let ymd = self.ymd();
+        write!(fmt, "{:04}-{:02}-{:02}", ymd.year, ymd.month, ymd.day)
+    }
+}
+
+pub fn game_date(input: &[u8]) -> IResult<&[u8], GameDate> {
+    map(le_u32, GameDate).parse(input)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_game_date() {
+        for (days, (year, month, day)) in [
+            (0, (0, 1, 1)),
+            (59, (0, 2, 29)),
+            (366, (1, 1, 1)),
+            (693961, (1900, 1, 1)),
+            (694020, (1900, 3, 1)),
+            (715875, (1960, 1, 1)),
+            (730484, (1999, 12, 31)),
+            (730544, (2000, 2, 29)),
+            (1826213, (5000, 1, 1)),
+        ] {
+            let date = GameDate(days);
+            let ymd = YearMonthDay { year, month, day };
+            assert_eq!(ymd, date.ymd(), "{}", days);
+            assert_eq!(Some(date), GameDate::from_ymd(year, month, day));
+            assert_eq!(
+                NaiveDate::from_ymd_opt(year as i32, month.into(), day.into()).unwrap(),
+                NaiveDate::try_from(date).unwrap()
+            );
+        }
+
+        assert!(is_leap_year(2000));
+        assert!(!is_leap_year(1900));
+        assert_eq!(None, GameDate::from_ymd(1900, 2, 29));
+        assert_eq!(None, GameDate::from_ymd(1960, 13, 1));
+
+        let date = GameDate::from_ymd(1960, 1, 31).unwrap();
+        assert_eq!("1960-01-31", date.to_string());
+        assert_eq!(GameDate::from_ymd(1960, 2, 29), date.checked_add_months(1));
+        assert_eq!(
+            GameDate::from_ymd(1959, 11, 30),
+            date.checked_add_months(-2)
+        );
+        assert_eq!(
+            GameDate::from_ymd(1961, 2, 28),
+            date.checked_add_months(1)
+                .and_then(|date| date.checked_add_years(1))
+        );
+        assert_eq!(GameDate::from_ymd(1960, 3, 1), date.checked_add_days(30));
+        assert_eq!(None, GameDate(0).checked_add_days(-1));
+        assert!(NaiveDate::try_from(GameDate(u32::MAX)).is_err());
+        let last = GameDate::try_from(NaiveDate::MAX).unwrap();
+        assert_eq!(NaiveDate::MAX, NaiveDate::try_from(last).unwrap());
+        assert!(NaiveDate::try_from(GameDate(last.0 + 1)).is_err());
+        assert_eq!("11759221-01-19", GameDate(u32::MAX).to_string());
+        assert_eq!(
+            366,
+            GameDate::from_ymd(1961, 1, 1).unwrap() - GameDate(715875)
+        );
+    }
+}
@@ -0,0 +1,92 @@
+use crate::{tcp::read_frame, GamePacket, ServerResponse};
+use anyhow::{bail, format_err};
+use std::time::Duration;
+use tokio::{
+    io::AsyncWriteExt,
+    net::{TcpStream, ToSocketAddrs},
+};
+
+/// Fetch server information over the game TCP port with `CLIENT_GAME_INFO`
+///
+/// Fails if the server has not answered within `timeout`, connecting included.
+pub async fn query_game_info<A: ToSocketAddrs>(
+    addr: A,
+    timeout: Duration,
+) -> anyhow::Result<ServerResponse> {
+    tokio::time::timeout(timeout, query(addr))
+        .await
+        .map_err(|_| format_err!("no game info received within {:?}", timeout))?
+}
+
+async fn query<A: ToSocketAddrs>(addr: A) -> anyhow::Result<ServerResponse> {
+    let mut stream = TcpStream::connect(addr).await?;
+    stream
+        .write_all(&GamePacket::ClientGameInfo.to_bytes()?)
+        .await?;
+
+    loop {
+        let frame = read_frame(&mut stream).await?;
+        let (_, packet) =
+            GamePacket::from_bytes(&frame).map_err(|e| format_err!("malformed packet: {:?}", e))?;
+
+        match packet {
+            GamePacket::ServerGameInfo(info) => return Ok(info),
+            GamePacket::ServerError(error) => bail!("server error: {}", error),
+            GamePacket::ServerFull => bail!("server is full"),
+            GamePacket::ServerBanned => bail!("banned from server"),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server_response;
+    use tokio::{io::AsyncReadExt, net::TcpListener};
+
+    #[tokio::test]
+    async fn test_query_game_info() {
+        let (_, expectation) = server_response::tests::fixtures();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let response = GamePacket::ServerGameInfo(expectation.clone())
+            .to_bytes()
+            .unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = [0; 3];
+            stream.read_exact(&mut request).await.unwrap();
+            assert_eq!(request, [3, 0, 7]);
+            stream.write_all(&response).await.unwrap();
+        });
+
+        let result = query_game_info(addr, Duration::from_secs(10))
+            .await
+            .unwrap();
+
+        assert_eq!(expectation, result);
+    }
+
+    #[tokio::test]
+    async fn test_query_game_info_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            // Answer with unrelated packets only
+            let (mut stream, _) = listener.accept().await.unwrap();
+            loop {
+                let packet = GamePacket::ServerNeedGamePassword.to_bytes().unwrap();
+                if stream.write_all(&packet).await.is_err() {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        });
+
+        assert!(query_game_info(addr, Duration::from_millis(100))
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,533 @@
+use crate::{
+    auth::*, client_info::*, frame::*, join::*, network_error::*, rcon::*, util::*, ServerResponse,
+};
+use byteorder::{LittleEndian, WriteBytesExt};
+use nom::{
+    self,
+    bytes::complete::take,
+    combinator::{map, map_opt},
+    number::complete::*,
+    *,
+};
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+use strum::EnumDiscriminants;
+
+/// Revision of the game protocol, which decides the packet type numbers
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum GameProtocolVersion {
+    /// OpenTTD 13 and older
+    #[default]
+    V13,
+    /// OpenTTD 14: authentication and encryption, with the name and company moved from
+    /// `CLIENT_JOIN` to `CLIENT_IDENTIFY`, and without the password packets
+    V14,
+}
+
+/// Packet types of OpenTTD 14 in wire order, as `PacketGameType` in `tcp_game.h`
+const V14_PACKET_TYPES: [GamePacketDiscriminants; 44] = {
+    use GamePacketDiscriminants as D;
+    [
+        D::ServerFull,
+        D::ServerBanned,
+        D::ClientJoinV14,
+        D::ServerError,
+        D::ClientUnused,
+        D::ServerUnused,
+        D::ServerGameInfo,
+        D::ClientGameInfo,
+        D::ServerNewgame,
+        D::ServerShutdown,
+        D::ServerAuthRequest,
+        D::ClientAuthResponse,
+        D::ServerEnableEncryption,
+        D::ClientIdentify,
+        D::ServerCheckNewgrfs,
+        D::ClientNewgrfsChecked,
+        D::ServerWelcome,
+        D::ServerClientInfo,
+        D::ClientGetmap,
+        D::ServerWait,
+        D::ServerMapBegin,
+        D::ServerMapSize,
+        D::ServerMapData,
+        D::ServerMapDone,
+        D::ClientMapOk,
+        D::ServerJoin,
+        D::ServerFrame,
+        D::ClientAck,
+        D::ServerSync,
+        D::ClientCommand,
+        D::ServerCommand,
+        D::ClientChat,
+        D::ServerChat,
+        D::ServerExternalChat,
+        D::ClientRcon,
+        D::ServerRcon,
+        D::ClientMove,
+        D::ServerMove,
+        D::ClientSetName,
+        D::ServerConfigUpdate,
+        D::ClientQuit,
+        D::ServerQuit,
+        D::ClientError,
+        D::ServerErrorQuit,
+    ]
+};
+
+impl GamePacketDiscriminants {
+    /// Type number on the wire, `None` for packets the protocol version lacks
+    pub fn packet_type(self, version: GameProtocolVersion) -> Option<u8> {
+        match version {
+            // Variants up to `SERVER_ERROR_QUIT` are numbered as in OpenTTD 13
+            GameProtocolVersion::V13 => {
+                Some(u8::from(self)).filter(|&v| v <= u8::from(Self::ServerErrorQuit))
+            }
+            GameProtocolVersion::V14 => V14_PACKET_TYPES
+                .iter()
+                .position(|&kind| kind == self)
+                .map(|v| v as u8),
+        }
+    }
+
+    /// Inverse of [`packet_type`](Self::packet_type)
+    pub fn from_packet_type(packet_type: u8, version: GameProtocolVersion) -> Option<Self> {
+        match version {
+            GameProtocolVersion::V13 => Self::try_from(packet_type)
+                .ok()
+                .filter(|kind| kind.packet_type(version).is_some()),
+            GameProtocolVersion::V14 => V14_PACKET_TYPES.get(usize::from(packet_type)).copied(),
+        }
+    }
+}
+
+/// OpenTTD game (TCP) network packet
+///
+/// The variant order follows the OpenTTD 13 packet types, followed by those added in OpenTTD
+/// 14; see [`GameProtocolVersion`] for how they are numbered on the wire. Unit variants are packets whose payload is not modelled
+/// yet; their body is skipped when parsing.
+#[derive(Clone, Debug, PartialEq, EnumDiscriminants)]
+#[strum_discriminants(repr(u8))]
+#[strum_discriminants(derive(IntoPrimitive, TryFromPrimitive))]
+pub enum GamePacket {
+    ServerFull,
+    ServerBanned,
+    ClientJoin(ClientJoinPacket),
+    ServerError(ServerErrorPacket),
+    ClientUnused,
+    ServerUnused,
+    ServerGameInfo(ServerResponse),
+    ClientGameInfo,
+    ServerNewgame,
+    ServerShutdown,
+    ServerCheckNewgrfs(ServerCheckNewgrfsPacket),
+    ClientNewgrfsChecked,
+    ServerNeedGamePassword,
+    ClientGamePassword(ClientPasswordPacket),
+    ServerNeedCompanyPassword(ServerNeedCompanyPasswordPacket),
+    ClientCompanyPassword(ClientPasswordPacket),
+    ServerWelcome(ServerWelcomePacket),
+    ServerClientInfo(ServerClientInfoPacket),
+    ClientGetmap,
+    ServerWait(ServerWaitPacket),
+    ServerMapBegin(ServerMapBeginPacket),
+    ServerMapSize(ServerMapSizePacket),
+    ServerMapData(ServerMapDataPacket),
+    ServerMapDone,
+    ClientMapOk,
+    ServerJoin(ServerJoinPacket),
+    ServerFrame(ServerFramePacket),
+    ClientAck(ClientAckPacket),
+    ServerSync,
+    ClientCommand,
+    ServerCommand,
+    ClientChat,
+    ServerChat,
+    ServerExternalChat,
+    ClientRcon(ClientRconPacket),
+    ServerRcon(ServerRconPacket),
+    ClientMove,
+    ServerMove(ServerMovePacket),
+    ClientSetPassword,
+    ClientSetName(ClientSetNamePacket),
+    ServerCompanyUpdate,
+    ServerConfigUpdate,
+    ClientQuit,
+    ServerQuit(ServerQuitPacket),
+    ClientError(ClientErrorPacket),
+    ServerErrorQuit(ServerErrorQuitPacket),
+    ServerAuthRequest(ServerAuthRequestPacket),
+    ClientAuthResponse(ClientAuthResponsePacket),
+    ServerEnableEncryption(ServerEnableEncryptionPacket),
+    ClientJoinV14(ClientJoinV14Packet),
+    ClientIdentify(ClientIdentifyPacket),
+}
+
+impl GamePacket {
+    /// Parse a single size-prefixed game packet of the OpenTTD 13 protocol
+    pub fn from_bytes(input: &[u8]) -> IResult<&[u8], GamePacket> {
+        Self::from_bytes_with_version(input, GameProtocolVersion::V13)
+    }
+
+    /// Parse a single size-prefixed game packet of the given protocol version
+    pub fn from_bytes_with_version(
+        input: &[u8],
+        version: GameProtocolVersion,
+    ) -> IResult<&[u8], GamePacket> {
+        let (input, size) = le_u16(input)?;
+        let (input, body) = take(usize::from(size).saturating_sub(2)).parse(input)?;
+        let (payload, packet_type) = map_opt(le_u8, |v| {
+            GamePacketDiscriminants::from_packet_type(v, version)
+        })
+        .parse(body)?;
+
+        use GamePacketDiscriminants as D;
+        let packet = match packet_type {
+            D::ClientJoin => {
+                map(ClientJoinPacket::from_bytes, Self::ClientJoin)
+                    .parse(payload)?
+                    .1
+            }
+            D::ServerError => {
+                map(ServerErrorPacket::from_bytes, Self::ServerError)
+                    .parse(payload)?
+                    .1
+            }
+            D::ServerGameInfo => {
+                map(ServerResponse::from_bytes, Self::ServerGameInfo)
+                    .parse(payload)?
+                    .1
+            }
+            D::ServerCheckNewgrfs => {
+                map(
+                    ServerCheckNewgrfsPacket::from_bytes,
+                    Self::ServerCheckNewgrfs,
+                )
+                .parse(payload)?
+                .1
+            }
+            D::ClientGamePassword => {
+                map(ClientPasswordPacket::from_bytes, Self::ClientGamePassword)
+                    .parse(payload)?
+                    .1
+            }
+            D::ServerNeedCompanyPassword => {
+                map(
+                    ServerNeedCompanyPasswordPacket::from_bytes,
+                    Self::ServerNeedCompanyPassword,
+                )
+                .parse(payload)?
+                .1
+            }
+            D::ClientCompanyPassword => {
+                map(
+                    ClientPasswordPacket::from_bytes,
+                    Self::ClientCompanyPassword,
+                )
+                .parse(payload)?
+                .1
+            }
+            D::ServerWelcome => {
+                map(ServerWelcomePacket::from_bytes, Self::ServerWelcome)
+                    .parse(payload)?
+                    .1
+            }
+            D::ServerClientInfo => {
+                map(ServerClientInfoPacket::from_bytes, Self::ServerClientInfo)
+                    .parse(payload)?
+                    .1
+            }
+            D::ServerWait => {
+                map(ServerWaitPacket::from_bytes, Self::ServerWait)
+                    .parse(payload)?
+                    .1
+            }
+            D::ServerMapBegin => {
+                map(ServerMapBeginPacket::from_bytes, Self::ServerMapBegin)
+                    .parse(payload)?
+                    .1
+            }
+            D::ServerMapSize => {
+                map(ServerMapSizePacket::from_bytes, Self::ServerMapSize)
+                    .parse(payload)?
+                    .1
+            }
+            D::ServerMapData => {
+                map(ServerMapDataPacket::from_bytes, Self::ServerMapData)
+                    .parse(payload)?
+                    .1
+            }
+            D::ServerJoin => {
+                map(ServerJoinPacket::from_bytes, Self::ServerJoin)
+                    .parse(payload)?
+                    .1
+            }
+            D::ServerFrame => {
+                map(ServerFramePacket::from_bytes, Self::ServerFrame)
+                    .parse(payload)?
+                    .1
+            }
+            D::ClientAck => {
+                map(ClientAckPacket::from_bytes, Self::ClientAck)
+                    .parse(payload)?
+                    .1
+            }
+            D::ClientRcon => {
+                map(ClientRconPacket::from_bytes, Self::ClientRcon)
+                    .parse(payload)?
+                    .1
+            }
+            D::ServerRcon => {
+                map(ServerRconPacket::from_bytes, Self::ServerRcon)
+                    .parse(payload)?
+                    .1
+            }
+            D::ServerMove => {
+                map(ServerMovePacket::from_bytes, Self::ServerMove)
+                    .parse(payload)?
+                    .1
+            }
+            D::ClientSetName => {
+                map(ClientSetNamePacket::from_bytes, Self::ClientSetName)
+                    .parse(payload)?
+                    .1
+            }
+            D::ServerQuit => {
+                map(ServerQuitPacket::from_bytes, Self::ServerQuit)
+                    .parse(payload)?
+                    .1
+            }
+            D::ClientError => {
+                map(ClientErrorPacket::from_bytes, Self::ClientError)
+                    .parse(payload)?
+                    .1
+            }
+            D::ServerErrorQuit => {
+                map(ServerErrorQuitPacket::from_bytes, Self::ServerErrorQuit)
+                    .parse(payload)?
+                    .1
+            }
+            D::ServerAuthRequest => {
+                map(ServerAuthRequestPacket::from_bytes, Self::ServerAuthRequest)
+                    .parse(payload)?
+                    .1
+            }
+            D::ClientAuthResponse => {
+                map(
+                    ClientAuthResponsePacket::from_bytes,
+                    Self::ClientAuthResponse,
+                )
+                .parse(payload)?
+                .1
+            }
+            D::ServerEnableEncryption => {
+                map(
+                    ServerEnableEncryptionPacket::from_bytes,
+                    Self::ServerEnableEncryption,
+                )
+                .parse(payload)?
+                .1
+            }
+            D::ClientJoinV14 => {
+                map(ClientJoinV14Packet::from_bytes, Self::ClientJoinV14)
+                    .parse(payload)?
+                    .1
+            }
+            D::ClientIdentify => {
+                map(ClientIdentifyPacket::from_bytes, Self::ClientIdentify)
+                    .parse(payload)?
+                    .1
+            }
+            D::ServerFull => Self::ServerFull,
+            D::ServerBanned => Self::ServerBanned,
+            D::ClientUnused => Self::ClientUnused,
+            D::ServerUnused => Self::ServerUnused,
+            D::ClientGameInfo => Self::ClientGameInfo,
+            D::ServerNewgame => Self::ServerNewgame,
+            D::ServerShutdown => Self::ServerShutdown,
+            D::ClientNewgrfsChecked => Self::ClientNewgrfsChecked,
+            D::ServerNeedGamePassword => Self::ServerNeedGamePassword,
+            D::ClientGetmap => Self::ClientGetmap,
+            D::ServerMapDone => Self::ServerMapDone,
+            D::ClientMapOk => Self::ClientMapOk,
+            D::ServerSync => Self::ServerSync,
+            D::ClientCommand => Self::ClientCommand,
+            D::ServerCommand => Self::ServerCommand,
+            D::ClientChat => Self::ClientChat,
+            D::ServerChat => Self::ServerChat,
+            D::ServerExternalChat => Self::ServerExternalChat,
+            D::ClientMove => Self::ClientMove,
+            D::ClientSetPassword => Self::ClientSetPassword,
+            D::ServerCompanyUpdate => Self::ServerCompanyUpdate,
+            D::ServerConfigUpdate => Self::ServerConfigUpdate,
+            D::ClientQuit => Self::ClientQuit,
+        };
+
+        Ok((input, packet))
+    }
+
+    /// Serialize for the OpenTTD 13 protocol
+    pub fn to_bytes(&self) -> std::io::Result<Vec<u8>> {
+        self.to_bytes_with_version(GameProtocolVersion::V13)
+    }
+
+    pub fn to_bytes_with_version(&self, version: GameProtocolVersion) -> std::io::Result<Vec<u8>> {
+        let kind = GamePacketDiscriminants::from(self);
+        let packet_type = kind.packet_type(version).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("{:?} does not exist in protocol {:?}", kind, version),
+            )
+        })?;
+        let buf = &mut vec![packet_type];
+
+        match self {
+            GamePacket::ClientJoin(data) => data.write_pkt(buf)?,
+            GamePacket::ServerError(data) => data.write_pkt(buf)?,
+            GamePacket::ServerGameInfo(data) => data.write_pkt(buf)?,
+            GamePacket::ServerCheckNewgrfs(data) => data.write_pkt(buf)?,
+            GamePacket::ClientGamePassword(data) => data.write_pkt(buf)?,
+            GamePacket::ServerNeedCompanyPassword(data) => data.write_pkt(buf)?,
+            GamePacket::ClientCompanyPassword(data) => data.write_pkt(buf)?,
+            GamePacket::ServerWelcome(data) => data.write_pkt(buf)?,
+            GamePacket::ServerClientInfo(data) => data.write_pkt(buf)?,
+            GamePacket::ServerWait(data) => data.write_pkt(buf)?,
+            GamePacket::ServerMapBegin(data) => data.write_pkt(buf)?,
+            GamePacket::ServerMapSize(data) => data.write_pkt(buf)?,
+            GamePacket::ServerMapData(data) => data.write_pkt(buf)?,
+            GamePacket::ServerJoin(data) => data.write_pkt(buf)?,
+            GamePacket::ServerFrame(data) => data.write_pkt(buf)?,
+            GamePacket::ClientAck(data) => data.write_pkt(buf)?,
+            GamePacket::ClientRcon(data) => data.write_pkt(buf)?,
+            GamePacket::ServerRcon(data) => data.write_pkt(buf)?,
+            GamePacket::ServerMove(data) => data.write_pkt(buf)?,
+            GamePacket::ClientSetName(data) => data.write_pkt(buf)?,
+            GamePacket::ServerQuit(data) => data.write_pkt(buf)?,
+            GamePacket::ClientError(data) => data.write_pkt(buf)?,
+            GamePacket::ServerErrorQuit(data) => data.write_pkt(buf)?,
+            GamePacket::ServerAuthRequest(data) => data.write_pkt(buf)?,
+            GamePacket::ClientAuthResponse(data) => data.write_pkt(buf)?,
+            GamePacket::ServerEnableEncryption(data) => data.write_pkt(buf)?,
+            GamePacket::ClientJoinV14(data) => data.write_pkt(buf)?,
+            GamePacket::ClientIdentify(data) => data.write_pkt(buf)?,
+            _ => {}
+        }
+
+        let mut out = vec![];
+        out.write_u16::<LittleEndian>(buf.len() as u16 + 2)?;
+        out.append(buf);
+
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex_literal::hex;
+    use std::ffi::CString;
+
+    #[test]
+    fn test_protocol_versions() {
+        use GamePacketDiscriminants as D;
+
+        // Numbers from `PacketGameType` of both releases
+        for (kind, v13, v14) in [
+            (D::ServerFull, Some(0), Some(0)),
+            (D::ClientJoin, Some(2), None),
+            (D::ClientJoinV14, None, Some(2)),
+            (D::ServerShutdown, Some(9), Some(9)),
+            (D::ServerAuthRequest, None, Some(10)),
+            (D::ClientAuthResponse, None, Some(11)),
+            (D::ServerEnableEncryption, None, Some(12)),
+            (D::ClientIdentify, None, Some(13)),
+            (D::ServerCheckNewgrfs, Some(10), Some(14)),
+            (D::ClientNewgrfsChecked, Some(11), Some(15)),
+            (D::ServerNeedGamePassword, Some(12), None),
+            (D::ClientGamePassword, Some(13), None),
+            (D::ServerNeedCompanyPassword, Some(14), None),
+            (D::ClientCompanyPassword, Some(15), None),
+            (D::ServerWelcome, Some(16), Some(16)),
+            (D::ServerFrame, Some(26), Some(26)),
+            (D::ClientRcon, Some(34), Some(34)),
+            (D::ServerMove, Some(37), Some(37)),
+            (D::ClientSetPassword, Some(38), None),
+            (D::ClientSetName, Some(39), Some(38)),
+            (D::ServerCompanyUpdate, Some(40), None),
+            (D::ServerConfigUpdate, Some(41), Some(39)),
+            (D::ServerErrorQuit, Some(45), Some(43)),
+        ] {
+            for (version, packet_type) in [
+                (GameProtocolVersion::V13, v13),
+                (GameProtocolVersion::V14, v14),
+            ] {
+                assert_eq!(packet_type, kind.packet_type(version), "{:?}", kind);
+                if let Some(packet_type) = packet_type {
+                    assert_eq!(
+                        Some(kind),
+                        D::from_packet_type(packet_type, version),
+                        "{:?}",
+                        kind
+                    );
+                }
+            }
+        }
+
+        assert_eq!(None, D::from_packet_type(44, GameProtocolVersion::V14));
+        assert_eq!(None, D::from_packet_type(46, GameProtocolVersion::V13));
+    }
+
+    #[test]
+    fn test_v14_packets() {
+        let join = ClientJoinPacket {
+            openttd_revision: CString::new("14.1").unwrap(),
+            newgrf_version: 0x1e00_0000,
+            client_name: CString::new("bot").unwrap(),
+            company: CompanyID::SPECTATOR,
+        };
+        let (join_v14, identify) = join.split_v14();
+        let mut enable_encryption = hex!("1b00 0c").to_vec();
+        enable_encryption.extend_from_slice(&[0xab; NONCE_SIZE]);
+
+        for (packet, bytes) in [
+            (
+                GamePacket::ClientJoinV14(join_v14),
+                hex!("0c00 02 31342e3100 0000001e").to_vec(),
+            ),
+            (
+                GamePacket::ClientIdentify(identify),
+                hex!("0800 0d 626f7400 ff").to_vec(),
+            ),
+            (
+                GamePacket::ServerEnableEncryption(ServerEnableEncryptionPacket {
+                    encryption_nonce: [0xab; NONCE_SIZE],
+                }),
+                enable_encryption,
+            ),
+            (
+                GamePacket::ServerErrorQuit(ServerErrorQuitPacket {
+                    client_id: ClientID(3),
+                    error: NetworkErrorCode::General,
+                }),
+                hex!("0800 2b 03000000 00").to_vec(),
+            ),
+        ] {
+            assert_eq!(
+                bytes,
+                packet
+                    .to_bytes_with_version(GameProtocolVersion::V14)
+                    .unwrap()
+            );
+            assert_eq!(
+                (&[][..], packet.clone()),
+                GamePacket::from_bytes_with_version(&bytes, GameProtocolVersion::V14).unwrap()
+            );
+        }
+
+        let (join, identify) = join.split_v14();
+        assert!(GamePacket::ClientJoinV14(join).to_bytes().is_err());
+        assert!(GamePacket::ClientIdentify(identify).to_bytes().is_err());
+        assert!(GamePacket::ServerNeedGamePassword
+            .to_bytes_with_version(GameProtocolVersion::V14)
+            .is_err());
+    }
+}
@@ -0,0 +1,641 @@
+use crate::{
+    auth::*, client_info::ClientID, frame::*, join::*, network_error::ServerErrorPacket,
+    GamePacket, GameProtocolVersion, NewGRFHash,
+};
+use anyhow::{bail, ensure, format_err};
+use std::{
+    collections::VecDeque,
+    ffi::CString,
+    fmt,
+    time::{Duration, Instant},
+};
+use x25519_dalek::StaticSecret;
+
+/// Frames between unsolicited `CLIENT_ACK`s, one in-game day like the OpenTTD client
+const ACK_INTERVAL: u32 = 74;
+
+/// Progress of the join sequence
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SessionState {
+    /// `CLIENT_JOIN` sent, waiting for the server to reply
+    Joining,
+    /// `CLIENT_AUTH_RESPONSE` sent, waiting for `SERVER_ENABLE_ENCRYPTION`
+    Authenticating,
+    /// Waiting for [`GameClientSession::confirm_newgrfs`]
+    CheckingNewGrfs,
+    /// Waiting for [`GameClientSession::send_game_password`]
+    GamePassword,
+    /// Waiting for [`GameClientSession::send_company_password`]
+    CompanyPassword,
+    /// Authorized, waiting for the map to arrive
+    DownloadingMap,
+    /// Map received, following the game frame by frame
+    Active,
+    Closed,
+}
+
+/// Something the caller should know about or react to
+#[derive(Clone, Debug, PartialEq)]
+pub enum SessionEvent {
+    /// The server requires these NewGRFs. Confirm once they are available.
+    NewGrfCheck(Vec<(u32, NewGRFHash)>),
+    /// Also sent for OpenTTD 14 servers that authenticate with the game password
+    GamePasswordRequired,
+    /// Hash the password with [`company_password_hash`] using these values
+    CompanyPasswordRequired(ServerNeedCompanyPasswordPacket),
+    Welcome(ServerWelcomePacket),
+    /// Other clients are downloading the map first
+    MapQueued(u8),
+    MapBegin(u32),
+    MapSize(u32),
+    MapData(Vec<u8>),
+    /// The whole map has been received and acknowledged
+    MapDone,
+    /// A packet not consumed by the session itself
+    Packet(GamePacket),
+    /// The server refused the connection with `SERVER_FULL` or `SERVER_BANNED`
+    Refused(GamePacket),
+    Error(ServerErrorPacket),
+    /// Nothing was received within the timeout while in the given state
+    TimedOut(SessionState),
+}
+
+/// Sans-IO game client that drives the join sequence.
+///
+/// Push received bytes with [`receive`](Self::receive), send whatever [`transmit`](Self::transmit)
+/// returns and react to [`poll_event`](Self::poll_event). Timeouts are checked against the
+/// instants handed in by the caller, so no clock or socket is used internally.
+///
+/// With [`GameProtocolVersion::V14`] the session also answers the server's authentication
+/// request, encrypts everything after `SERVER_ENABLE_ENCRYPTION` and then identifies itself with
+/// the name and company of the join packet.
+#[derive(Debug)]
+pub struct GameClientSession {
+    state: SessionState,
+    protocol: GameProtocolVersion,
+    auth: Authentication,
+    /// Sent once encryption is enabled; OpenTTD 14 only
+    identify: Option<ClientIdentifyPacket>,
+    timeout: Duration,
+    deadline: Instant,
+    recv_buf: Vec<u8>,
+    send_buf: Vec<u8>,
+    events: VecDeque<SessionEvent>,
+    client_id: Option<ClientID>,
+    frame_counter: u32,
+    last_ack_frame: u32,
+}
+
+/// OpenTTD 14 key exchange and the encryption it sets up
+#[derive(Default)]
+struct Authentication {
+    secret_key: Option<StaticSecret>,
+    /// Request waiting for the game password
+    request: Option<ServerAuthRequestPacket>,
+    /// Keys of the last `CLIENT_AUTH_RESPONSE`
+    keys: Option<AuthKeys>,
+    transport: Option<EncryptedTransport>,
+}
+
+impl fmt::Debug for Authentication {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        fmt.debug_struct("Authentication")
+            .field("request", &self.request)
+            .field("encrypted", &self.transport.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl GameClientSession {
+    /// Start joining an OpenTTD 13 server; `CLIENT_JOIN` is queued for transmission right away
+    pub fn new(join: ClientJoinPacket, timeout: Duration, now: Instant) -> std::io::Result<Self> {
+        Self::with_protocol(join, GameProtocolVersion::V13, timeout, now)
+    }
+
+    /// Start joining a server that speaks `protocol`
+    pub fn with_protocol(
+        join: ClientJoinPacket,
+        protocol: GameProtocolVersion,
+        timeout: Duration,
+        now: Instant,
+    ) -> std::io::Result<Self> {
+        let (join, identify) = match protocol {
+            GameProtocolVersion::V13 => (GamePacket::ClientJoin(join), None),
+            GameProtocolVersion::V14 => {
+                let (join, identify) = join.split_v14();
+                (GamePacket::ClientJoinV14(join), Some(identify))
+            }
+        };
+        let mut session = Self {
+            state: SessionState::Joining,
+            protocol,
+            auth: Authentication::default(),
+            identify,
+            timeout,
+            deadline: now + timeout,
+            recv_buf: vec![],
+            send_buf: vec![],
+            events: VecDeque::new(),
+            client_id: None,
+            frame_counter: 0,
+            last_ack_frame: 0,
+        };
+        session.send(&join)?;
+
+        Ok(session)
+    }
+
+    /// Long-term key for servers that authenticate clients by their public key
+    pub fn set_secret_key(&mut self, secret_key: StaticSecret) {
+        self.auth.secret_key = Some(secret_key);
+    }
+
+    pub fn state(&self) -> SessionState {
+        self.state
+    }
+
+    /// ID assigned by the server in `SERVER_WELCOME`
+    pub fn client_id(&self) -> Option<ClientID> {
+        self.client_id
+    }
+
+    /// Last frame the server allowed the client to run
+    pub fn frame_counter(&self) -> u32 {
+        self.frame_counter
+    }
+
+    /// Feed bytes read from the connection
+    pub fn receive(&mut self, data: &[u8], now: Instant) -> anyhow::Result<()> {
+        ensure!(self.state != SessionState::Closed, "session is closed");
+
+        self.recv_buf.extend_from_slice(data);
+        while self.recv_buf.len() >= 2 {
+            let size = usize::from(u16::from_le_bytes([self.recv_buf[0], self.recv_buf[1]]));
+            ensure!(size >= 3, "invalid packet size {}", size);
+            if self.recv_buf.len() < size {
+                break;
+            }
+
+            let mut frame = self.recv_buf.drain(..size).collect::<Vec<_>>();
+            if let Some(transport) = &mut self.auth.transport {
+                frame = transport.open(&frame)?;
+            }
+            let (_, packet) = GamePacket::from_bytes_with_version(&frame, self.protocol)
+                .map_err(|e| format_err!("malformed packet: {:?}", e))?;
+
+            self.deadline = now + self.timeout;
+            self.handle_packet(packet)?;
+
+            if self.state == SessionState::Closed {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Take the bytes that should be written to the connection
+    pub fn transmit(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.send_buf)
+    }
+
+    pub fn poll_event(&mut self) -> Option<SessionEvent> {
+        self.events.pop_front()
+    }
+
+    /// When [`handle_timeout`](Self::handle_timeout) should be called next
+    pub fn poll_timeout(&self) -> Option<Instant> {
+        (self.state != SessionState::Closed).then_some(self.deadline)
+    }
+
+    pub fn handle_timeout(&mut self, now: Instant) {
+        if self.state != SessionState::Closed && now >= self.deadline {
+            self.events.push_back(SessionEvent::TimedOut(self.state));
+            self.state = SessionState::Closed;
+        }
+    }
+
+    /// Queue an arbitrary packet, e.g. chat or RCON once the session is active
+    pub fn send(&mut self, packet: &GamePacket) -> std::io::Result<()> {
+        let mut frame = packet.to_bytes_with_version(self.protocol)?;
+        if let Some(transport) = &mut self.auth.transport {
+            frame = transport.seal(&frame)?;
+        }
+        self.send_buf.extend_from_slice(&frame);
+
+        Ok(())
+    }
+
+    /// Tell the server all NewGRFs from [`SessionEvent::NewGrfCheck`] are available
+    pub fn confirm_newgrfs(&mut self) -> anyhow::Result<()> {
+        self.expect_state(SessionState::CheckingNewGrfs)?;
+        self.send(&GamePacket::ClientNewgrfsChecked)?;
+        self.state = SessionState::Joining;
+
+        Ok(())
+    }
+
+    pub fn send_game_password(&mut self, password: CString) -> anyhow::Result<()> {
+        self.expect_state(SessionState::GamePassword)?;
+        match self.auth.request.take() {
+            Some(request) => self.send_auth_response(&request, password.to_str()?)?,
+            None => {
+                self.send(&GamePacket::ClientGamePassword(ClientPasswordPacket {
+                    password,
+                }))?;
+                self.state = SessionState::Joining;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn send_auth_response(
+        &mut self,
+        request: &ServerAuthRequestPacket,
+        password: &str,
+    ) -> anyhow::Result<()> {
+        let (response, keys) =
+            client_auth_response(request, self.auth.secret_key.as_ref(), password)?;
+        self.auth.keys = Some(keys);
+        self.send(&GamePacket::ClientAuthResponse(response))?;
+        self.state = SessionState::Authenticating;
+
+        Ok(())
+    }
+
+    /// Send the company password, already hashed with [`company_password_hash`]
+    pub fn send_company_password(&mut self, password: CString) -> anyhow::Result<()> {
+        self.expect_state(SessionState::CompanyPassword)?;
+        self.send(&GamePacket::ClientCompanyPassword(ClientPasswordPacket {
+            password,
+        }))?;
+        self.state = SessionState::Joining;
+
+        Ok(())
+    }
+
+    fn expect_state(&self, state: SessionState) -> anyhow::Result<()> {
+        if self.state != state {
+            bail!("expected session state {:?}, got {:?}", state, self.state);
+        }
+
+        Ok(())
+    }
+
+    fn handle_packet(&mut self, packet: GamePacket) -> anyhow::Result<()> {
+        use SessionState::*;
+
+        let event = match (self.state, packet) {
+            (_, packet @ (GamePacket::ServerFull | GamePacket::ServerBanned)) => {
+                self.state = Closed;
+                SessionEvent::Refused(packet)
+            }
+            (_, GamePacket::ServerError(error)) => {
+                self.state = Closed;
+                SessionEvent::Error(error)
+            }
+            // The server may ask again after a failed attempt
+            (Joining | Authenticating, GamePacket::ServerAuthRequest(p)) => {
+                if p.method == NetworkAuthenticationMethod::X25519Pake {
+                    self.state = GamePassword;
+                    self.auth.request = Some(p);
+                    SessionEvent::GamePasswordRequired
+                } else {
+                    self.send_auth_response(&p, "")?;
+                    return Ok(());
+                }
+            }
+            (Authenticating, GamePacket::ServerEnableEncryption(p)) => {
+                let keys = self
+                    .auth
+                    .keys
+                    .take()
+                    .ok_or_else(|| format_err!("encryption enabled before authentication"))?;
+                self.auth.transport = Some(EncryptedTransport::client(&keys, &p.encryption_nonce));
+                self.state = Joining;
+                if let Some(identify) = self.identify.take() {
+                    self.send(&GamePacket::ClientIdentify(identify))?;
+                }
+                return Ok(());
+            }
+            (Joining, GamePacket::ServerCheckNewgrfs(p)) => {
+                self.state = CheckingNewGrfs;
+                SessionEvent::NewGrfCheck(p.newgrfs)
+            }
+            (Joining, GamePacket::ServerNeedGamePassword) => {
+                self.state = GamePassword;
+                SessionEvent::GamePasswordRequired
+            }
+            (Joining, GamePacket::ServerNeedCompanyPassword(p)) => {
+                self.state = CompanyPassword;
+                SessionEvent::CompanyPasswordRequired(p)
+            }
+            (Joining, GamePacket::ServerWelcome(p)) => {
+                self.state = DownloadingMap;
+                self.client_id = Some(p.client_id);
+                self.send(&GamePacket::ClientGetmap)?;
+                SessionEvent::Welcome(p)
+            }
+            (DownloadingMap, GamePacket::ServerWait(p)) => {
+                SessionEvent::MapQueued(p.clients_waiting)
+            }
+            (DownloadingMap, GamePacket::ServerMapBegin(p)) => {
+                self.frame_counter = p.frame_counter;
+                self.last_ack_frame = p.frame_counter;
+                SessionEvent::MapBegin(p.frame_counter)
+            }
+            (DownloadingMap, GamePacket::ServerMapSize(p)) => SessionEvent::MapSize(p.bytes_total),
+            (DownloadingMap, GamePacket::ServerMapData(p)) => SessionEvent::MapData(p.data),
+            (DownloadingMap, GamePacket::ServerMapDone) => {
+                self.state = Active;
+                self.send(&GamePacket::ClientMapOk)?;
+                SessionEvent::MapDone
+            }
+            (Active, GamePacket::ServerFrame(p)) => {
+                self.frame_counter = p.frame_counter_max;
+                // Frame counters wrap around in long running games
+                if p.token.is_some()
+                    || p.frame_counter_server.wrapping_sub(self.last_ack_frame) >= ACK_INTERVAL
+                {
+                    self.last_ack_frame = p.frame_counter_server;
+                    self.send(&GamePacket::ClientAck(ClientAckPacket {
+                        frame_counter: p.frame_counter_server,
+                        token: p.token.unwrap_or_default(),
+                    }))?;
+                }
+                return Ok(());
+            }
+            (_, packet) => SessionEvent::Packet(packet),
+        };
+        self.events.push_back(event);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CompanyID, NetworkErrorCode};
+
+    fn join() -> ClientJoinPacket {
+        ClientJoinPacket {
+            openttd_revision: CString::new("13.4").unwrap(),
+            newgrf_version: 0x1d00_0000,
+            client_name: CString::new("bot").unwrap(),
+            company: CompanyID::SPECTATOR,
+        }
+    }
+
+    fn recording(packets: &[GamePacket]) -> Vec<u8> {
+        packets
+            .iter()
+            .flat_map(|packet| packet.to_bytes().unwrap())
+            .collect()
+    }
+
+    fn events(session: &mut GameClientSession) -> Vec<SessionEvent> {
+        std::iter::from_fn(|| session.poll_event()).collect()
+    }
+
+    #[test]
+    fn test_join_sequence() {
+        let now = Instant::now();
+        let mut session = GameClientSession::new(join(), Duration::from_secs(10), now).unwrap();
+        assert_eq!(
+            session.transmit(),
+            GamePacket::ClientJoin(join()).to_bytes().unwrap()
+        );
+
+        let newgrfs = vec![(0x00074e44, NewGRFHash([1; 16]))];
+        let input = recording(&[GamePacket::ServerCheckNewgrfs(ServerCheckNewgrfsPacket {
+            newgrfs: newgrfs.clone(),
+        })]);
+        session.receive(&input, now).unwrap();
+        assert_eq!(events(&mut session), [SessionEvent::NewGrfCheck(newgrfs)]);
+        session.confirm_newgrfs().unwrap();
+        assert_eq!(
+            session.transmit(),
+            GamePacket::ClientNewgrfsChecked.to_bytes().unwrap()
+        );
+
+        session
+            .receive(&recording(&[GamePacket::ServerNeedGamePassword]), now)
+            .unwrap();
+        assert_eq!(events(&mut session), [SessionEvent::GamePasswordRequired]);
+        session
+            .send_game_password(CString::new("hunter2").unwrap())
+            .unwrap();
+        session.transmit();
+
+        let welcome = ServerWelcomePacket {
+            client_id: ClientID(7),
+            generation_seed: 1234,
+            server_id: CString::new("0123456789abcdef").unwrap(),
+        };
+        let input = recording(&[
+            GamePacket::ServerWelcome(welcome.clone()),
+            GamePacket::ServerMapBegin(ServerMapBeginPacket { frame_counter: 500 }),
+            GamePacket::ServerMapSize(ServerMapSizePacket { bytes_total: 3 }),
+            GamePacket::ServerMapData(ServerMapDataPacket {
+                data: vec![1, 2, 3],
+            }),
+            GamePacket::ServerMapDone,
+            GamePacket::ServerFrame(ServerFramePacket {
+                frame_counter_server: 501,
+                frame_counter_max: 510,
+                token: Some(42),
+            }),
+        ]);
+        // Byte by byte, as a slow connection would deliver it
+        for byte in input.chunks(1) {
+            session.receive(byte, now).unwrap();
+        }
+
+        assert_eq!(
+            events(&mut session),
+            [
+                SessionEvent::Welcome(welcome),
+                SessionEvent::MapBegin(500),
+                SessionEvent::MapSize(3),
+                SessionEvent::MapData(vec![1, 2, 3]),
+                SessionEvent::MapDone,
+            ]
+        );
+        assert_eq!(
+            session.transmit(),
+            recording(&[
+                GamePacket::ClientGetmap,
+                GamePacket::ClientMapOk,
+                GamePacket::ClientAck(ClientAckPacket {
+                    frame_counter: 501,
+                    token: 42
+                }),
+            ])
+        );
+        assert_eq!(session.state(), SessionState::Active);
+        assert_eq!(session.client_id(), Some(ClientID(7)));
+        assert_eq!(session.frame_counter(), 510);
+    }
+
+    #[test]
+    fn test_join_sequence_v14() {
+        use x25519_dalek::PublicKey;
+        const V14: GameProtocolVersion = GameProtocolVersion::V14;
+
+        let now = Instant::now();
+        let mut session =
+            GameClientSession::with_protocol(join(), V14, Duration::from_secs(10), now).unwrap();
+        let (join_v14, identify) = join().split_v14();
+        assert_eq!(
+            session.transmit(),
+            GamePacket::ClientJoinV14(join_v14)
+                .to_bytes_with_version(V14)
+                .unwrap()
+        );
+
+        let server_secret = StaticSecret::from([5; 32]);
+        let request = ServerAuthRequestPacket {
+            method: NetworkAuthenticationMethod::X25519Pake,
+            public_key: PublicKey::from(&server_secret).to_bytes(),
+            key_exchange_nonce: [7; NONCE_SIZE],
+        };
+        let input = GamePacket::ServerAuthRequest(request.clone())
+            .to_bytes_with_version(V14)
+            .unwrap();
+        session.receive(&input, now).unwrap();
+        assert_eq!(events(&mut session), [SessionEvent::GamePasswordRequired]);
+        session
+            .send_game_password(CString::new("hunter2").unwrap())
+            .unwrap();
+        assert_eq!(session.state(), SessionState::Authenticating);
+
+        let response = match GamePacket::from_bytes_with_version(&session.transmit(), V14) {
+            Ok((_, GamePacket::ClientAuthResponse(response))) => response,
+            other => panic!("unexpected packet {:?}", other),
+        };
+        let keys = verify_auth_response(&request, &server_secret, &response, "hunter2").unwrap();
+
+        // Everything after SERVER_ENABLE_ENCRYPTION is encrypted, even in the same read
+        let nonce = [9; NONCE_SIZE];
+        let mut server = EncryptedTransport::server(&keys, &nonce);
+        let newgrfs = vec![(0x00074e44, NewGRFHash([1; 16]))];
+        let mut input = GamePacket::ServerEnableEncryption(ServerEnableEncryptionPacket {
+            encryption_nonce: nonce,
+        })
+        .to_bytes_with_version(V14)
+        .unwrap();
+        let check = GamePacket::ServerCheckNewgrfs(ServerCheckNewgrfsPacket {
+            newgrfs: newgrfs.clone(),
+        });
+        input.extend(
+            server
+                .seal(&check.to_bytes_with_version(V14).unwrap())
+                .unwrap(),
+        );
+        session.receive(&input, now).unwrap();
+        assert_eq!(events(&mut session), [SessionEvent::NewGrfCheck(newgrfs)]);
+
+        session.confirm_newgrfs().unwrap();
+        let output = session.transmit();
+        let mut packets = vec![];
+        let mut rest = &output[..];
+        while !rest.is_empty() {
+            let size = usize::from(u16::from_le_bytes([rest[0], rest[1]]));
+            let plain = server.open(&rest[..size]).unwrap();
+            packets.push(GamePacket::from_bytes_with_version(&plain, V14).unwrap().1);
+            rest = &rest[size..];
+        }
+        assert_eq!(
+            packets,
+            [
+                GamePacket::ClientIdentify(identify),
+                GamePacket::ClientNewgrfsChecked
+            ]
+        );
+    }
+
+    #[test]
+    fn test_frame_counter_wraps() {
+        let now = Instant::now();
+        let mut session = GameClientSession::new(join(), Duration::from_secs(10), now).unwrap();
+        let frame = |frame_counter_server| {
+            GamePacket::ServerFrame(ServerFramePacket {
+                frame_counter_server,
+                frame_counter_max: frame_counter_server,
+                token: None,
+            })
+        };
+        let input = recording(&[
+            GamePacket::ServerWelcome(ServerWelcomePacket {
+                client_id: ClientID(7),
+                generation_seed: 1234,
+                server_id: CString::new("0123456789abcdef").unwrap(),
+            }),
+            GamePacket::ServerMapBegin(ServerMapBeginPacket {
+                frame_counter: u32::MAX - 10,
+            }),
+            GamePacket::ServerMapDone,
+            frame(u32::MAX),
+        ]);
+        session.receive(&input, now).unwrap();
+        session.transmit();
+
+        session.receive(&recording(&[frame(62)]), now).unwrap();
+        assert!(session.transmit().is_empty());
+        session.receive(&recording(&[frame(63)]), now).unwrap();
+        assert_eq!(
+            session.transmit(),
+            recording(&[GamePacket::ClientAck(ClientAckPacket {
+                frame_counter: 63,
+                token: 0
+            })])
+        );
+    }
+
+    #[test]
+    fn test_server_error() {
+        let now = Instant::now();
+        let mut session = GameClientSession::new(join(), Duration::from_secs(10), now).unwrap();
+
+        let error = ServerErrorPacket {
+            error: NetworkErrorCode::WrongRevision,
+            reason: None,
+        };
+        session
+            .receive(&recording(&[GamePacket::ServerError(error.clone())]), now)
+            .unwrap();
+
+        assert_eq!(events(&mut session), [SessionEvent::Error(error)]);
+        assert_eq!(session.state(), SessionState::Closed);
+        assert!(session.receive(&[], now).is_err());
+    }
+
+    #[test]
+    fn test_timeout() {
+        let now = Instant::now();
+        let mut session = GameClientSession::new(join(), Duration::from_secs(10), now).unwrap();
+
+        session.handle_timeout(now + Duration::from_secs(5));
+        assert_eq!(session.poll_event(), None);
+
+        session
+            .receive(
+                &recording(&[GamePacket::ServerNeedGamePassword]),
+                now + Duration::from_secs(5),
+            )
+            .unwrap();
+        assert_eq!(session.poll_timeout(), Some(now + Duration::from_secs(15)));
+
+        session.handle_timeout(now + Duration::from_secs(15));
+        assert_eq!(
+            events(&mut session),
+            [
+                SessionEvent::GamePasswordRequired,
+                SessionEvent::TimedOut(SessionState::GamePassword)
+            ]
+        );
+        assert_eq!(session.poll_timeout(), None);
+    }
+}
@@ -0,0 +1,385 @@
+use crate::{
+    client_info::*,
+    newgrf::{newgrf_entry, NewGRFHash},
+    util::*,
+};
+use byteorder::{LittleEndian, WriteBytesExt};
+use md5::{Digest, Md5};
+use nom::{
+    self,
+    combinator::{map, opt, rest},
+    multi::count,
+    number::complete::*,
+    sequence::tuple,
+    *,
+};
+use std::ffi::CString;
+
+/// Length of the server ID used to salt company passwords, without the terminator
+const NETWORK_SERVER_ID_LENGTH: usize = 32;
+
+/// `CLIENT_JOIN`: first packet a client sends after connecting
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClientJoinPacket {
+    pub openttd_revision: CString,
+    pub newgrf_version: u32,
+    pub client_name: CString,
+    /// Company to join, or [`CompanyID::SPECTATOR`] / [`CompanyID::NEW_COMPANY`]
+    pub company: CompanyID,
+}
+
+impl ByteWriter for ClientJoinPacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.extend_from_slice(self.openttd_revision.to_bytes_with_nul());
+        buf.write_u32::<LittleEndian>(self.newgrf_version)?;
+        buf.extend_from_slice(self.client_name.to_bytes_with_nul());
+        buf.write_u8(self.company.0)?;
+        // Used to be the client language
+        buf.write_u8(0)?;
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for ClientJoinPacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(
+            tuple((read_cstring, le_u32, read_cstring, company_id, opt(le_u8))),
+            |(openttd_revision, newgrf_version, client_name, company, _)| Self {
+                openttd_revision,
+                newgrf_version,
+                client_name,
+                company,
+            },
+        )
+        .parse(input)
+    }
+}
+
+impl ClientJoinPacket {
+    /// The same join as OpenTTD 14 sends it: `CLIENT_JOIN`, then `CLIENT_IDENTIFY` once the
+    /// connection is encrypted
+    pub fn split_v14(&self) -> (ClientJoinV14Packet, ClientIdentifyPacket) {
+        (
+            ClientJoinV14Packet {
+                openttd_revision: self.openttd_revision.clone(),
+                newgrf_version: self.newgrf_version,
+            },
+            ClientIdentifyPacket {
+                client_name: self.client_name.clone(),
+                company: self.company,
+            },
+        )
+    }
+}
+
+/// `CLIENT_JOIN` of OpenTTD 14, without the name and company of [`ClientIdentifyPacket`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClientJoinV14Packet {
+    pub openttd_revision: CString,
+    pub newgrf_version: u32,
+}
+
+impl ByteWriter for ClientJoinV14Packet {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.extend_from_slice(self.openttd_revision.to_bytes_with_nul());
+        buf.write_u32::<LittleEndian>(self.newgrf_version)?;
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for ClientJoinV14Packet {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(
+            tuple((read_cstring, le_u32)),
+            |(openttd_revision, newgrf_version)| Self {
+                openttd_revision,
+                newgrf_version,
+            },
+        )
+        .parse(input)
+    }
+}
+
+/// `CLIENT_IDENTIFY`: name and company, sent once authentication is done (OpenTTD 14)
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClientIdentifyPacket {
+    pub client_name: CString,
+    /// Company to join, or [`CompanyID::SPECTATOR`] / [`CompanyID::NEW_COMPANY`]
+    pub company: CompanyID,
+}
+
+impl ByteWriter for ClientIdentifyPacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.extend_from_slice(self.client_name.to_bytes_with_nul());
+        buf.write_u8(self.company.0)?;
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for ClientIdentifyPacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(
+            tuple((read_cstring, company_id)),
+            |(client_name, company)| Self {
+                client_name,
+                company,
+            },
+        )
+        .parse(input)
+    }
+}
+
+/// `SERVER_CHECK_NEWGRFS`: NewGRFs the client must have to join
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerCheckNewgrfsPacket {
+    /// In load order
+    pub newgrfs: Vec<(u32, NewGRFHash)>,
+}
+
+impl ByteWriter for ServerCheckNewgrfsPacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.write_u8(self.newgrfs.len().try_into().map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "NewGRF maximum number is 255",
+            )
+        })?)?;
+        for &(id, hash) in &self.newgrfs {
+            buf.write_u32::<LittleEndian>(id)?;
+            buf.extend_from_slice(&hash.0);
+        }
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for ServerCheckNewgrfsPacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        let (input, num) = le_u8(input)?;
+        map(count(newgrf_entry, num.into()), |newgrfs| Self { newgrfs }).parse(input)
+    }
+}
+
+/// `CLIENT_GAME_PASSWORD` and `CLIENT_COMPANY_PASSWORD`
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClientPasswordPacket {
+    pub password: CString,
+}
+
+impl ByteWriter for ClientPasswordPacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.extend_from_slice(self.password.to_bytes_with_nul());
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for ClientPasswordPacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(read_cstring, |password| Self { password }).parse(input)
+    }
+}
+
+/// `SERVER_NEED_COMPANY_PASSWORD`: salt for hashing the company password
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerNeedCompanyPasswordPacket {
+    pub generation_seed: u32,
+    pub server_id: CString,
+}
+
+impl ByteWriter for ServerNeedCompanyPasswordPacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.write_u32::<LittleEndian>(self.generation_seed)?;
+        buf.extend_from_slice(self.server_id.to_bytes_with_nul());
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for ServerNeedCompanyPasswordPacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(
+            tuple((le_u32, read_cstring)),
+            |(generation_seed, server_id)| Self {
+                generation_seed,
+                server_id,
+            },
+        )
+        .parse(input)
+    }
+}
+
+/// Hash a company password the way OpenTTD does before sending it to the server
+pub fn company_password_hash(password: &str, server_id: &str, generation_seed: u32) -> CString {
+    if password.is_empty() {
+        return CString::default();
+    }
+
+    let password = password.as_bytes();
+    let server_id = server_id.as_bytes();
+    let salted = (0..NETWORK_SERVER_ID_LENGTH)
+        .map(|i| {
+            let password_char = password.get(i).copied().unwrap_or(0);
+            let server_id_char = server_id.get(i).copied().unwrap_or(0);
+            let seed_char = (generation_seed >> (i % 32)) as u8;
+            password_char ^ server_id_char ^ seed_char
+        })
+        .collect::<Vec<_>>();
+
+    let hex = Md5::digest(salted)
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<String>();
+    CString::new(hex).unwrap()
+}
+
+/// `SERVER_WELCOME`: the client is authorized and may request the map
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerWelcomePacket {
+    pub client_id: ClientID,
+    pub generation_seed: u32,
+    pub server_id: CString,
+}
+
+impl ByteWriter for ServerWelcomePacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.write_u32::<LittleEndian>(self.client_id.0)?;
+        buf.write_u32::<LittleEndian>(self.generation_seed)?;
+        buf.extend_from_slice(self.server_id.to_bytes_with_nul());
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for ServerWelcomePacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(
+            tuple((client_id, le_u32, read_cstring)),
+            |(client_id, generation_seed, server_id)| Self {
+                client_id,
+                generation_seed,
+                server_id,
+            },
+        )
+        .parse(input)
+    }
+}
+
+/// `SERVER_WAIT`: other clients are downloading the map first
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerWaitPacket {
+    pub clients_waiting: u8,
+}
+
+impl ByteWriter for ServerWaitPacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.write_u8(self.clients_waiting)?;
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for ServerWaitPacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(le_u8, |clients_waiting| Self { clients_waiting }).parse(input)
+    }
+}
+
+/// `SERVER_MAP_BEGIN`: frame the savegame was made at
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerMapBeginPacket {
+    pub frame_counter: u32,
+}
+
+impl ByteWriter for ServerMapBeginPacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.write_u32::<LittleEndian>(self.frame_counter)?;
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for ServerMapBeginPacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(le_u32, |frame_counter| Self { frame_counter }).parse(input)
+    }
+}
+
+/// `SERVER_MAP_SIZE`: total size of the compressed savegame
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerMapSizePacket {
+    pub bytes_total: u32,
+}
+
+impl ByteWriter for ServerMapSizePacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.write_u32::<LittleEndian>(self.bytes_total)?;
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for ServerMapSizePacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(le_u32, |bytes_total| Self { bytes_total }).parse(input)
+    }
+}
+
+/// `SERVER_MAP_DATA`: a chunk of the compressed savegame
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerMapDataPacket {
+    pub data: Vec<u8>,
+}
+
+impl ByteWriter for ServerMapDataPacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.extend_from_slice(&self.data);
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for ServerMapDataPacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(rest, |data: &[u8]| Self {
+            data: data.to_vec(),
+        })
+        .parse(input)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_company_password_hash() {
+        assert_eq!(company_password_hash("", "abc", 1), CString::default());
+
+        let hash = company_password_hash("secret", "0123456789abcdef0123456789abcdef", 42);
+        assert_eq!(hash.as_bytes().len(), 32);
+        assert!(hash
+            .to_str()
+            .unwrap()
+            .chars()
+            .all(|c| c.is_ascii_digit() || c.is_ascii_uppercase()));
+        assert_ne!(
+            hash,
+            company_password_hash("secret", "0123456789abcdef0123456789abcdef", 43)
+        );
+
+        // Known answers of OpenTTD's GenerateCompanyPasswordHash: 32 salted
+        // bytes, MD5, upper case hex
+        assert_eq!(
+            CString::new("70D646DCD54313337ABA1A2A3CFF78D2").unwrap(),
+            hash
+        );
+        assert_eq!(
+            CString::new("8E9FB7606476DA361B09943479DFB603").unwrap(),
+            company_password_hash("hunter2", "a3c1f0e2b4d6987f01234567890abcde", 0x9e3779b9)
+        );
+    }
+}
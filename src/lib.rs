@@ -1,18 +1,76 @@
 #![allow(unreachable_code)]
 
+#[cfg(feature = "tokio")]
+mod admin_client;
+mod admin_command;
+#[cfg(feature = "admin-mock")]
+mod admin_mock;
+mod admin_packet;
+mod admin_server_packet;
+mod auth;
+mod chat;
 mod client_get_list;
+mod client_info;
+mod content;
+mod content_download;
+mod content_http;
+mod content_info;
+#[cfg(feature = "content-mock")]
+mod content_mock;
+mod frame;
+mod game_date;
+#[cfg(feature = "tokio")]
+mod game_info;
+mod game_packet;
+mod game_session;
+mod join;
 mod master_response_list;
+mod network_error;
 mod newgrf;
+mod newgrf_check;
+mod newgrf_config;
+mod newgrf_file;
+mod newgrf_header;
+mod rcon;
 mod server_detail_info;
 mod server_register;
 mod server_response;
 mod server_unregister;
+#[cfg(feature = "tokio")]
+mod tcp;
 mod util;
 
+#[cfg(feature = "admin-mock")]
+pub use crate::admin_mock::*;
+#[cfg(feature = "content-mock")]
+pub use crate::content_mock::*;
+#[cfg(feature = "tokio")]
+pub use crate::{admin_client::*, game_info::*};
 pub use crate::{
+    admin_command::*,
+    admin_packet::*,
+    admin_server_packet::*,
+    auth::*,
+    chat::*,
     client_get_list::*,
+    client_info::*,
+    content::*,
+    content_download::*,
+    content_http::*,
+    content_info::*,
+    frame::*,
+    game_date::*,
+    game_packet::*,
+    game_session::*,
+    join::*,
     master_response_list::*,
-    newgrf::{ActiveNewGrf, NewGRFHash},
+    network_error::*,
+    newgrf::{ActiveNewGrf, GrfId, NewGRFHash},
+    newgrf_check::*,
+    newgrf_config::*,
+    newgrf_file::*,
+    newgrf_header::*,
+    rcon::*,
     server_detail_info::*,
     server_register::*,
     server_response::{ProtocolVer, ServerResponse},
@@ -28,7 +86,7 @@ use nom::{
     *,
 };
 use num_enum::{IntoPrimitive, TryFromPrimitive};
-use std::{collections::BTreeMap, ffi::CString};
+use std::ffi::CString;
 use strum::EnumDiscriminants;
 
 /// OpenTTD UDP network packet
@@ -180,7 +238,8 @@ impl PacketPayload for GcListingServer {
 #[derive(Clone, Debug, PartialEq)]
 pub struct GcNewgrfLookupPacket {
     pub newgrf_lookup_table_cursor: u32,
-    pub newgrfs: BTreeMap<u32, (u32, NewGRFHash, CString)>,
+    /// Lookup table index and NewGRF, in the order the coordinator sent them
+    pub newgrfs: Vec<(u32, (u32, NewGRFHash, CString))>,
 }
 
 impl ByteWriter for GcNewgrfLookupPacket {
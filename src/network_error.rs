@@ -0,0 +1,223 @@
+use crate::util::*;
+use byteorder::WriteBytesExt;
+use nom::{
+    self,
+    combinator::{map, opt},
+    number::complete::*,
+    sequence::tuple,
+    *,
+};
+use num_enum::{FromPrimitive, IntoPrimitive};
+use std::{ffi::CString, fmt};
+
+/// Reason a game connection was refused or dropped
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, IntoPrimitive, FromPrimitive)]
+#[repr(u8)]
+pub enum NetworkErrorCode {
+    General,
+    Desync,
+    SavegameFailed,
+    ConnectionLost,
+    IllegalPacket,
+    NewgrfMismatch,
+    NotAuthorized,
+    NotExpected,
+    WrongRevision,
+    NameInUse,
+    WrongPassword,
+    CompanyMismatch,
+    Kicked,
+    Cheater,
+    Full,
+    TooManyCommands,
+    TimeoutPassword,
+    TimeoutComputer,
+    TimeoutMap,
+    TimeoutJoin,
+    InvalidClientName,
+    NotOnAllowList,
+    NoAuthenticationMethodAvailable,
+    /// Code added by a newer OpenTTD
+    #[num_enum(catch_all)]
+    Unknown(u8),
+}
+
+impl fmt::Display for NetworkErrorCode {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        use NetworkErrorCode::*;
+
+        fmt.write_str(match *self {
+            General => "general error",
+            Desync => "desync error",
+            SavegameFailed => "could not load map",
+            ConnectionLost => "connection lost",
+            IllegalPacket => "protocol error",
+            NewgrfMismatch => "NewGRF mismatch",
+            NotAuthorized => "not authorized",
+            NotExpected => "received invalid or unexpected packet",
+            WrongRevision => "wrong revision",
+            NameInUse => "name already in use",
+            WrongPassword => "wrong password",
+            CompanyMismatch => "wrong company in DoCommand",
+            Kicked => "kicked by server",
+            Cheater => "was trying to use a cheat",
+            Full => "server full",
+            TooManyCommands => "was sending too many commands",
+            TimeoutPassword => "received no password in time",
+            TimeoutComputer => "general timeout",
+            TimeoutMap => "downloading map took too long",
+            TimeoutJoin => "processing map took too long",
+            InvalidClientName => "invalid client name",
+            NotOnAllowList => "not on the allow list",
+            NoAuthenticationMethodAvailable => "no authentication method available",
+            Unknown(code) => return write!(fmt, "unknown error {}", code),
+        })
+    }
+}
+
+pub fn network_error_code(input: &[u8]) -> IResult<&[u8], NetworkErrorCode> {
+    map(le_u8, NetworkErrorCode::from).parse(input)
+}
+
+/// `SERVER_ERROR`: the server refuses or drops the connection
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerErrorPacket {
+    pub error: NetworkErrorCode,
+    /// Free-form explanation, sent by servers along with some errors such as kicks
+    pub reason: Option<CString>,
+}
+
+impl fmt::Display for ServerErrorPacket {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match &self.reason {
+            Some(reason) => write!(fmt, "{}: {}", self.error, reason.to_string_lossy()),
+            None => write!(fmt, "{}", self.error),
+        }
+    }
+}
+
+impl ByteWriter for ServerErrorPacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.write_u8(self.error.into())?;
+        if let Some(reason) = &self.reason {
+            buf.extend_from_slice(reason.to_bytes_with_nul());
+        }
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for ServerErrorPacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(
+            tuple((network_error_code, opt(read_cstring))),
+            |(error, reason)| Self { error, reason },
+        )
+        .parse(input)
+    }
+}
+
+/// `CLIENT_ERROR`: the client reports that it hit an error and is leaving
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClientErrorPacket {
+    pub error: NetworkErrorCode,
+}
+
+impl ByteWriter for ClientErrorPacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.write_u8(self.error.into())?;
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for ClientErrorPacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(network_error_code, |error| Self { error }).parse(input)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::GamePacket;
+    use hex_literal::hex;
+
+    fn fixtures() -> Vec<(Vec<u8>, GamePacket)> {
+        vec![
+            (
+                hex!("0400 03 0a").into(),
+                GamePacket::ServerError(ServerErrorPacket {
+                    error: NetworkErrorCode::WrongPassword,
+                    reason: None,
+                }),
+            ),
+            (
+                hex!("0a00 03 0c 7370616d6d00").into(),
+                GamePacket::ServerError(ServerErrorPacket {
+                    error: NetworkErrorCode::Kicked,
+                    reason: Some(CString::new("spamm").unwrap()),
+                }),
+            ),
+            (
+                hex!("0400 03 15").into(),
+                GamePacket::ServerError(ServerErrorPacket {
+                    error: NetworkErrorCode::NotOnAllowList,
+                    reason: None,
+                }),
+            ),
+            (
+                hex!("0400 03 40").into(),
+                GamePacket::ServerError(ServerErrorPacket {
+                    error: NetworkErrorCode::Unknown(0x40),
+                    reason: None,
+                }),
+            ),
+            (
+                hex!("0400 2c 01").into(),
+                GamePacket::ClientError(ClientErrorPacket {
+                    error: NetworkErrorCode::Desync,
+                }),
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_parse_error_packets() {
+        for (input, expectation) in fixtures() {
+            let result = GamePacket::from_bytes(&input).unwrap();
+
+            assert_eq!(expectation, result.1);
+        }
+    }
+
+    #[test]
+    fn test_write_error_packets() {
+        for (expectation, input) in fixtures() {
+            let result = input.to_bytes().unwrap();
+
+            assert_eq!(expectation, result);
+        }
+    }
+
+    #[test]
+    fn test_error_message() {
+        let error = ServerErrorPacket {
+            error: NetworkErrorCode::Kicked,
+            reason: Some(CString::new("spamming chat").unwrap()),
+        };
+
+        assert_eq!(error.to_string(), "kicked by server: spamming chat");
+        assert_eq!(NetworkErrorCode::from(14), NetworkErrorCode::Full);
+        assert_eq!(
+            NetworkErrorCode::from(22),
+            NetworkErrorCode::NoAuthenticationMethodAvailable
+        );
+        assert_eq!(NetworkErrorCode::from(200), NetworkErrorCode::Unknown(200));
+        assert_eq!(200, u8::from(NetworkErrorCode::Unknown(200)));
+        assert_eq!(
+            NetworkErrorCode::Unknown(200).to_string(),
+            "unknown error 200"
+        );
+    }
+}
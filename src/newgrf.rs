@@ -1,4 +1,5 @@
 use crate::util::*;
+use anyhow::ensure;
 use nom::{
     self,
     bytes::complete::take,
@@ -9,14 +10,10 @@ use nom::{
     *,
 };
 use num_enum::{IntoPrimitive, TryFromPrimitive};
-use std::{
-    collections::{BTreeMap, BTreeSet},
-    ffi::CString,
-    fmt,
-};
+use std::{ffi::CString, fmt, str::FromStr};
 use strum::EnumDiscriminants;
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct NewGRFHash(pub [u8; 16]);
 
 impl fmt::Display for NewGRFHash {
@@ -28,13 +25,116 @@ impl fmt::Display for NewGRFHash {
     }
 }
 
+/// Parse 32 hex digits, as written by `Display`
+impl FromStr for NewGRFHash {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ensure!(
+            s.len() == 32 && s.bytes().all(|c| c.is_ascii_hexdigit()),
+            "NewGRF MD5 must be 32 hex digits: {:?}",
+            s
+        );
+        let mut out = [0; 16];
+        for (i, byte) in out.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16)?;
+        }
+        Ok(Self(out))
+    }
+}
+
+impl TryFrom<&[u8]> for NewGRFHash {
+    type Error = std::array::TryFromSliceError;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        Ok(Self(value.try_into()?))
+    }
+}
+
+impl From<[u8; 16]> for NewGRFHash {
+    fn from(value: [u8; 16]) -> Self {
+        Self(value)
+    }
+}
+
+impl From<NewGRFHash> for [u8; 16] {
+    fn from(value: NewGRFHash) -> Self {
+        value.0
+    }
+}
+
+/// GRF ID of a NewGRF as read from the file, little endian
+///
+/// OpenTTD shows GRF IDs byte-swapped, so that they read in file order:
+/// `0x0103474d` is displayed and parsed as `4D470301`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct GrfId(pub u32);
+
+impl fmt::Display for GrfId {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(fmt, "{:08X}", self.0.swap_bytes())
+    }
+}
+
+impl FromStr for GrfId {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ensure!(
+            s.len() == 8 && s.bytes().all(|c| c.is_ascii_hexdigit()),
+            "GRF ID must be 8 hex digits: {:?}",
+            s
+        );
+        Ok(Self(u32::from_str_radix(s, 16)?.swap_bytes()))
+    }
+}
+
+impl From<u32> for GrfId {
+    fn from(value: u32) -> Self {
+        Self(value)
+    }
+}
+
+impl From<GrfId> for u32 {
+    fn from(value: GrfId) -> Self {
+        value.0
+    }
+}
+
+/// Both serialize as their `Display` strings
+#[cfg(feature = "serde")]
+macro_rules! serde_via_str {
+    ($t:ty) => {
+        impl serde::Serialize for $t {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_str(self)
+            }
+        }
+
+        impl<'de> serde::Deserialize<'de> for $t {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                String::deserialize(deserializer)?
+                    .parse()
+                    .map_err(serde::de::Error::custom)
+            }
+        }
+    };
+}
+
+#[cfg(feature = "serde")]
+serde_via_str!(NewGRFHash);
+#[cfg(feature = "serde")]
+serde_via_str!(GrfId);
+
+/// NewGRFs of a server in load order, duplicates included
 #[derive(Clone, Debug, PartialEq, EnumDiscriminants)]
 #[strum_discriminants(repr(u8))]
 #[strum_discriminants(derive(IntoPrimitive, TryFromPrimitive))]
 pub enum ActiveNewGrf {
-    OnlyId(BTreeMap<u32, NewGRFHash>),
-    Full(BTreeMap<u32, (NewGRFHash, CString)>),
-    Lookup(BTreeSet<u32>),
+    OnlyId(Vec<(u32, NewGRFHash)>),
+    Full(Vec<(u32, (NewGRFHash, CString))>),
+    /// Indices into the Game Coordinator's NewGRF lookup table
+    Lookup(Vec<u32>),
 }
 
 pub fn newgrf_md5(input: &[u8]) -> IResult<&[u8], NewGRFHash> {
@@ -63,23 +163,46 @@ impl ActiveNewGrf {
 
         match kind {
             ActiveNewGrfDiscriminants::Full => {
-                map(count(newgrf_entry_full, active_newgrf_num), |newgrf_data| {
-                    Self::Full(newgrf_data.into_iter().collect())
-                })
-                .parse(input)
+                map(count(newgrf_entry_full, active_newgrf_num), Self::Full).parse(input)
             }
             ActiveNewGrfDiscriminants::OnlyId => {
-                map(count(newgrf_entry, active_newgrf_num), |newgrf_data| {
-                    Self::OnlyId(newgrf_data.into_iter().collect())
-                })
-                .parse(input)
+                map(count(newgrf_entry, active_newgrf_num), Self::OnlyId).parse(input)
             }
             ActiveNewGrfDiscriminants::Lookup => {
-                map(count(le_u32, active_newgrf_num), |newgrf_data| {
-                    Self::Lookup(newgrf_data.into_iter().collect())
-                })
-                .parse(input)
+                map(count(le_u32, active_newgrf_num), Self::Lookup).parse(input)
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_newgrf_identity_strings() {
+        let grfid = GrfId(0x0103474d);
+        assert_eq!("4D470301", grfid.to_string());
+        assert_eq!(grfid, "4d470301".parse().unwrap());
+        assert!("4D4703".parse::<GrfId>().is_err());
+        assert!("4D47030G".parse::<GrfId>().is_err());
+        assert!("+D470301".parse::<GrfId>().is_err());
+
+        let md5 = NewGRFHash(*b"\x01\x23\x45\x67\x89\xab\xcd\xef\x01\x23\x45\x67\x89\xab\xcd\xef");
+        let s = "0123456789abcdef0123456789abcdef";
+        assert_eq!(s, md5.to_string());
+        assert_eq!(md5, s.parse().unwrap());
+        assert_eq!(md5, s.to_uppercase().parse().unwrap());
+        assert!(s[1..].parse::<NewGRFHash>().is_err());
+        assert!(NewGRFHash::try_from(&md5.0[1..]).is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_newgrf_identity_serde() {
+        let value = (GrfId(0x0103474d), NewGRFHash([0xab; 16]));
+        let json = serde_json::to_string(&value).unwrap();
+        assert_eq!(r#"["4D470301","abababababababababababababababab"]"#, json);
+        assert_eq!(value, serde_json::from_str(&json).unwrap());
+    }
+}
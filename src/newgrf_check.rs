@@ -0,0 +1,197 @@
+use crate::{
+    newgrf::{ActiveNewGrf, NewGRFHash},
+    newgrf_file::LocalNewGrf,
+};
+use std::{collections::BTreeMap, ffi::CString};
+
+/// Game Coordinator NewGRF lookup table, collected from `GC_NEWGRF_LOOKUP` packets
+pub type NewGrfLookupTable = BTreeMap<u32, (u32, NewGRFHash, CString)>;
+
+/// Local NewGRFs by GRF ID
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NewGrfIndex {
+    by_grfid: BTreeMap<u32, Vec<LocalNewGrf>>,
+}
+
+impl NewGrfIndex {
+    pub fn insert(&mut self, newgrf: LocalNewGrf) {
+        self.by_grfid.entry(newgrf.grfid).or_default().push(newgrf);
+    }
+
+    /// The NewGRF a server lists with `grfid` and `md5`
+    pub fn get(&self, grfid: u32, md5: &NewGRFHash) -> Option<&LocalNewGrf> {
+        self.versions(grfid)
+            .iter()
+            .find(|newgrf| newgrf.md5 == *md5)
+    }
+
+    /// All local NewGRFs with this GRF ID
+    pub fn versions(&self, grfid: u32) -> &[LocalNewGrf] {
+        self.by_grfid.get(&grfid).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &LocalNewGrf> {
+        self.by_grfid.values().flatten()
+    }
+}
+
+impl FromIterator<LocalNewGrf> for NewGrfIndex {
+    fn from_iter<I: IntoIterator<Item = LocalNewGrf>>(iter: I) -> Self {
+        let mut out = Self::default();
+        for newgrf in iter {
+            out.insert(newgrf);
+        }
+        out
+    }
+}
+
+/// How a server's NewGRF is available locally, as in the join dialog
+#[derive(Clone, Debug, PartialEq)]
+pub enum NewGrfStatus {
+    /// Same GRF ID and MD5
+    Found(LocalNewGrf),
+    /// Same GRF ID, but only other versions
+    VersionMismatch(Vec<LocalNewGrf>),
+    /// Not available locally, but the server named it, so it can be searched for
+    Missing,
+    /// Neither available locally nor named by the server
+    Unknown,
+}
+
+/// One NewGRF of a server, in load order
+#[derive(Clone, Debug, PartialEq)]
+pub struct NewGrfCheck {
+    /// GRF ID and MD5; `None` for lookup indices missing from the table
+    pub newgrf: Option<(u32, NewGRFHash)>,
+    pub name: Option<CString>,
+    pub status: NewGrfStatus,
+}
+
+impl NewGrfCheck {
+    fn new(index: &NewGrfIndex, newgrf: Option<(u32, NewGRFHash)>, name: Option<CString>) -> Self {
+        let status = match newgrf {
+            Some((grfid, md5)) => match index.get(grfid, &md5) {
+                Some(local) => NewGrfStatus::Found(local.clone()),
+                None if !index.versions(grfid).is_empty() => {
+                    NewGrfStatus::VersionMismatch(index.versions(grfid).to_vec())
+                }
+                None if name.as_ref().is_some_and(|name| !name.is_empty()) => NewGrfStatus::Missing,
+                None => NewGrfStatus::Unknown,
+            },
+            None => NewGrfStatus::Unknown,
+        };
+
+        Self {
+            newgrf,
+            name,
+            status,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NewGrfCheckReport {
+    pub entries: Vec<NewGrfCheck>,
+}
+
+impl NewGrfCheckReport {
+    /// Compare a server's NewGRFs against the local ones
+    ///
+    /// `lookup` resolves [`ActiveNewGrf::Lookup`] indices and may be empty for
+    /// the other variants.
+    pub fn new(active: &ActiveNewGrf, index: &NewGrfIndex, lookup: &NewGrfLookupTable) -> Self {
+        let check = |newgrf, name| NewGrfCheck::new(index, newgrf, name);
+        let entries = match active {
+            ActiveNewGrf::OnlyId(newgrfs) => newgrfs
+                .iter()
+                .map(|&newgrf| check(Some(newgrf), None))
+                .collect(),
+            ActiveNewGrf::Full(newgrfs) => newgrfs
+                .iter()
+                .map(|(grfid, (md5, name))| check(Some((*grfid, *md5)), Some(name.clone())))
+                .collect(),
+            ActiveNewGrf::Lookup(indices) => indices
+                .iter()
+                .map(|index| match lookup.get(index) {
+                    Some((grfid, md5, name)) => check(Some((*grfid, *md5)), Some(name.clone())),
+                    None => check(None, None),
+                })
+                .collect(),
+        };
+
+        Self { entries }
+    }
+
+    /// Whether every NewGRF is available in the exact version
+    pub fn is_compatible(&self) -> bool {
+        self.entries
+            .iter()
+            .all(|entry| matches!(entry.status, NewGrfStatus::Found(_)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use maplit::btreemap;
+
+    fn local(grfid: u32, md5: u8) -> LocalNewGrf {
+        LocalNewGrf {
+            path: format!("{:08x}.grf", grfid).into(),
+            tar_entry: None,
+            grfid,
+            md5: NewGRFHash([md5; 16]),
+        }
+    }
+
+    #[test]
+    fn test_newgrf_check() {
+        let index = [local(1, 1), local(2, 1), local(2, 2)]
+            .into_iter()
+            .collect::<NewGrfIndex>();
+        let name = |s: &str| CString::new(s).unwrap();
+        let active = ActiveNewGrf::Full(vec![
+            (1, (NewGRFHash([1; 16]), name("One"))),
+            (2, (NewGRFHash([3; 16]), name("Two"))),
+            (3, (NewGRFHash([3; 16]), name("Three"))),
+            (4, (NewGRFHash([4; 16]), name(""))),
+        ]);
+
+        let report = NewGrfCheckReport::new(&active, &index, &NewGrfLookupTable::new());
+        assert_eq!(
+            vec![
+                NewGrfStatus::Found(local(1, 1)),
+                NewGrfStatus::VersionMismatch(vec![local(2, 1), local(2, 2)]),
+                NewGrfStatus::Missing,
+                NewGrfStatus::Unknown,
+            ],
+            report
+                .entries
+                .iter()
+                .map(|entry| entry.status.clone())
+                .collect::<Vec<_>>()
+        );
+        assert!(!report.is_compatible());
+
+        let lookup = btreemap! {10 => (1, NewGRFHash([1; 16]), name("One"))};
+        let report = NewGrfCheckReport::new(&ActiveNewGrf::Lookup(vec![10, 11]), &index, &lookup);
+        assert_eq!(
+            vec![
+                NewGrfCheck {
+                    newgrf: Some((1, NewGRFHash([1; 16]))),
+                    name: Some(name("One")),
+                    status: NewGrfStatus::Found(local(1, 1)),
+                },
+                NewGrfCheck {
+                    newgrf: None,
+                    name: None,
+                    status: NewGrfStatus::Unknown,
+                },
+            ],
+            report.entries
+        );
+
+        let active = ActiveNewGrf::OnlyId(vec![(1, NewGRFHash([1; 16]))]);
+        assert!(NewGrfCheckReport::new(&active, &index, &lookup).is_compatible());
+    }
+}
@@ -0,0 +1,366 @@
+use crate::{
+    newgrf::{ActiveNewGrf, GrfId, NewGRFHash},
+    newgrf_check::*,
+    server_response::ServerResponse,
+};
+use anyhow::{bail, ensure, format_err};
+use std::{fmt::Write, path::Path};
+
+const NEWGRF_SECTION: &str = "newgrf";
+const NEWGRF_STATIC_SECTION: &str = "newgrf-static";
+
+/// One line of a NewGRF section: `grfid|md5|path = params`
+///
+/// OpenTTD also accepts `grfid|path` and a plain `path`, leaving out the MD5
+/// or both.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NewGrfConfigEntry {
+    /// `None` for old entries listing only a path
+    pub grfid: Option<u32>,
+    /// Only present together with the GRF ID
+    pub md5: Option<NewGRFHash>,
+    /// Path relative to a NewGRF search directory, or inside a tar archive
+    pub path: String,
+    pub params: Vec<u32>,
+}
+
+impl NewGrfConfigEntry {
+    fn parse(key: &str, value: Option<&str>) -> anyhow::Result<Self> {
+        let mut parts = key.splitn(3, '|');
+        let (grfid, md5, path) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(grfid), Some(md5), Some(path)) => {
+                let grfid = grfid.parse::<GrfId>()?.0;
+                (Some(grfid), Some(md5.parse()?), path)
+            }
+            (Some(grfid), Some(path), None) => (Some(grfid.parse::<GrfId>()?.0), None, path),
+            (Some(path), None, None) => (None, None, path),
+            _ => bail!("malformed NewGRF entry {:?}", key),
+        };
+        ensure!(!path.is_empty(), "NewGRF entry {:?} has no path", key);
+
+        let params = value
+            .unwrap_or_default()
+            .split([',', ' ', '\t'])
+            .filter(|v| !v.is_empty())
+            .map(|v| {
+                v.parse()
+                    .map_err(|_| format_err!("invalid NewGRF parameter {:?} of {:?}", v, key))
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(Self {
+            grfid,
+            md5,
+            path: path.to_string(),
+            params,
+        })
+    }
+
+    fn key(&self) -> String {
+        match (self.grfid, self.md5) {
+            (Some(grfid), Some(md5)) => format!("{}|{}|{}", GrfId(grfid), md5, self.path),
+            (Some(grfid), None) => format!("{}|{}", GrfId(grfid), self.path),
+            (None, _) => self.path.clone(),
+        }
+    }
+}
+
+/// `[newgrf]` and `[newgrf-static]` sections of `openttd.cfg`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NewGrfConfig {
+    /// NewGRFs of new games, in load order
+    pub newgrf: Vec<NewGrfConfigEntry>,
+    /// NewGRFs loaded into every game and not sent to clients
+    pub newgrf_static: Vec<NewGrfConfigEntry>,
+}
+
+impl NewGrfConfig {
+    /// Read the NewGRF sections of an `openttd.cfg`, ignoring all others
+    pub fn parse(cfg: &str) -> anyhow::Result<Self> {
+        let mut out = Self::default();
+        let mut section = None;
+        for line in cfg.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with([';', '#']) {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+                section = match name {
+                    NEWGRF_SECTION => Some(&mut out.newgrf),
+                    NEWGRF_STATIC_SECTION => Some(&mut out.newgrf_static),
+                    _ => None,
+                };
+                continue;
+            }
+
+            if let Some(entries) = &mut section {
+                let (key, value) = ini_item(line);
+                entries.push(NewGrfConfigEntry::parse(key, value)?);
+            }
+        }
+
+        Ok(out)
+    }
+
+    /// Both sections in `openttd.cfg` syntax
+    pub fn to_cfg(&self) -> String {
+        let mut out = String::new();
+        for (name, entries) in [
+            (NEWGRF_SECTION, &self.newgrf),
+            (NEWGRF_STATIC_SECTION, &self.newgrf_static),
+        ] {
+            let _ = writeln!(out, "[{}]", name);
+            for entry in entries {
+                let key = entry.key();
+                let params = entry
+                    .params
+                    .iter()
+                    .map(u32::to_string)
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                let line = if key.contains(' ') {
+                    format!("\"{}\" = {}", key, params)
+                } else {
+                    format!("{} = {}", key, params)
+                };
+                let _ = writeln!(out, "{}", line.trim_end());
+            }
+            out.push('\n');
+        }
+
+        out
+    }
+
+    /// `cfg` with its NewGRF sections replaced by these, other sections kept as they are
+    pub fn replace_in(&self, cfg: &str) -> String {
+        let mut out = String::new();
+        let mut skip = false;
+        for line in cfg.lines() {
+            let trimmed = line.trim();
+            if let Some(name) = trimmed.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+                skip = name == NEWGRF_SECTION || name == NEWGRF_STATIC_SECTION;
+            }
+            if !skip {
+                out.push_str(line);
+                out.push('\n');
+            }
+        }
+        if !out.is_empty() && !out.ends_with("\n\n") {
+            out.push('\n');
+        }
+        out.push_str(&self.to_cfg());
+
+        out
+    }
+
+    /// NewGRFs a server with this config lists to clients
+    ///
+    /// Entries without GRF ID and MD5 cannot be listed and are skipped.
+    pub fn active_newgrf(&self) -> ActiveNewGrf {
+        ActiveNewGrf::OnlyId(
+            self.newgrf
+                .iter()
+                .filter_map(|entry| entry.grfid.zip(entry.md5))
+                .collect(),
+        )
+    }
+
+    /// Config loading exactly the NewGRFs of a listed server
+    ///
+    /// Every NewGRF must be available locally in the same version, see
+    /// [`NewGrfCheckReport`]. Loose `.grf` files must lie in `search_dir`, the
+    /// directory `index` was scanned from, as OpenTTD looks their path up there.
+    /// Servers do not announce NewGRF parameters, so the defaults are used.
+    pub fn from_server(
+        response: &ServerResponse,
+        index: &NewGrfIndex,
+        lookup: &NewGrfLookupTable,
+        search_dir: &Path,
+    ) -> anyhow::Result<Self> {
+        let report = NewGrfCheckReport::new(&response.active_newgrf, index, lookup);
+        let newgrf = report
+            .entries
+            .into_iter()
+            .map(|entry| match entry.status {
+                NewGrfStatus::Found(local) => {
+                    let path = match &local.tar_entry {
+                        Some(tar_entry) => tar_entry.as_path(),
+                        None => local.path.strip_prefix(search_dir).map_err(|_| {
+                            format_err!("NewGRF {:?} is outside of {:?}", local.path, search_dir)
+                        })?,
+                    };
+                    Ok(NewGrfConfigEntry {
+                        grfid: Some(local.grfid),
+                        md5: Some(local.md5),
+                        path: config_path(path),
+                        params: vec![],
+                    })
+                }
+                status => Err(format_err!(
+                    "NewGRF {:?} ({:?}) is not available locally: {:?}",
+                    entry.newgrf,
+                    entry.name,
+                    status
+                )),
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(Self {
+            newgrf,
+            newgrf_static: vec![],
+        })
+    }
+}
+
+/// Relative path with `/` separators, which OpenTTD accepts on every platform
+fn config_path(path: &Path) -> String {
+    path.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Key and value of an ini line, either of which may be quoted
+fn ini_item(line: &str) -> (&str, Option<&str>) {
+    let (key, rest) = match line.strip_prefix('"') {
+        Some(quoted) => match quoted.split_once('"') {
+            Some((key, rest)) => (key, rest),
+            None => (quoted, ""),
+        },
+        None => {
+            let end = line.find(['=', ' ', '\t']).unwrap_or(line.len());
+            line.split_at(end)
+        }
+    };
+
+    let value = rest.trim_start_matches(['=', ' ', '\t']).trim_end();
+    let value = value
+        .strip_prefix('"')
+        .map(|v| v.strip_suffix('"').unwrap_or(v))
+        .unwrap_or(value);
+
+    (key, (!value.is_empty()).then_some(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::newgrf_file::LocalNewGrf;
+
+    #[test]
+    fn test_newgrf_config() {
+        let cfg = "[misc]\n\
+                   display_opt = SHOW_TOWN_NAMES\n\
+                   \n\
+                   [newgrf]\n\
+                   ; comment\n\
+                   4D470301|0123456789abcdef0123456789abcdef|opengfx/ogfx.grf = 1 2,3\n\
+                   \"4D470302|0123456789ABCDEF0123456789ABCDEF|my set/set.grf\" =\n\
+                   4D470304|no_md5.grf\n\
+                   old.grf = \"4\"\n\
+                   \n\
+                   [newgrf-static]\n\
+                   4D470303|00000000000000000000000000000000|static.grf = \n\
+                   [gui]\n\
+                   autosave = monthly\n";
+        let md5 = "0123456789abcdef0123456789abcdef".parse().unwrap();
+        let expectation = NewGrfConfig {
+            newgrf: vec![
+                NewGrfConfigEntry {
+                    grfid: Some(0x0103474d),
+                    md5: Some(md5),
+                    path: "opengfx/ogfx.grf".into(),
+                    params: vec![1, 2, 3],
+                },
+                NewGrfConfigEntry {
+                    grfid: Some(0x0203474d),
+                    md5: Some(md5),
+                    path: "my set/set.grf".into(),
+                    params: vec![],
+                },
+                NewGrfConfigEntry {
+                    grfid: Some(0x0403474d),
+                    md5: None,
+                    path: "no_md5.grf".into(),
+                    params: vec![],
+                },
+                NewGrfConfigEntry {
+                    grfid: None,
+                    md5: None,
+                    path: "old.grf".into(),
+                    params: vec![4],
+                },
+            ],
+            newgrf_static: vec![NewGrfConfigEntry {
+                grfid: Some(0x0303474d),
+                md5: Some(NewGRFHash([0; 16])),
+                path: "static.grf".into(),
+                params: vec![],
+            }],
+        };
+
+        let config = NewGrfConfig::parse(cfg).unwrap();
+        assert_eq!(expectation, config);
+        assert_eq!(
+            ActiveNewGrf::OnlyId(vec![(0x0103474d, md5), (0x0203474d, md5)]),
+            config.active_newgrf()
+        );
+
+        let replaced = config.replace_in(cfg);
+        assert!(replaced.starts_with("[misc]\ndisplay_opt = SHOW_TOWN_NAMES\n\n[gui]\n"));
+        assert!(replaced.contains(
+            "[newgrf]\n4D470301|0123456789abcdef0123456789abcdef|opengfx/ogfx.grf = 1 2 3\n"
+        ));
+        assert!(replaced.contains("\n4D470304|no_md5.grf =\n"));
+        assert_eq!(config, NewGrfConfig::parse(&replaced).unwrap());
+
+        assert!(NewGrfConfig::parse("[newgrf]\n4D470301|0123|a.grf =\n").is_err());
+        assert!(NewGrfConfig::parse("[newgrf]\na.grf = x\n").is_err());
+        assert!(NewGrfConfig::parse("[newgrf]\nxyz|a.grf =\n").is_err());
+    }
+
+    #[test]
+    fn test_newgrf_config_from_server() {
+        let md5 = NewGRFHash([1; 16]);
+        let search_dir = Path::new("/data/newgrf");
+        let index = [
+            LocalNewGrf {
+                path: "/data/newgrf/set.tar".into(),
+                tar_entry: Some("set/set.grf".into()),
+                grfid: 0x0103474d,
+                md5,
+            },
+            LocalNewGrf {
+                path: "/data/newgrf/loose/loose.grf".into(),
+                tar_entry: None,
+                grfid: 0x0203474d,
+                md5,
+            },
+            LocalNewGrf {
+                path: "/elsewhere/other.grf".into(),
+                tar_entry: None,
+                grfid: 0x0303474d,
+                md5,
+            },
+        ]
+        .into_iter()
+        .collect::<NewGrfIndex>();
+        let lookup = NewGrfLookupTable::new();
+        let mut response = crate::server_response::tests::fixtures().1;
+
+        response.active_newgrf = ActiveNewGrf::OnlyId(vec![(0x0103474d, md5), (0x0203474d, md5)]);
+        let config = NewGrfConfig::from_server(&response, &index, &lookup, search_dir).unwrap();
+        assert_eq!(
+            "[newgrf]\n\
+             4D470301|01010101010101010101010101010101|set/set.grf =\n\
+             4D470302|01010101010101010101010101010101|loose/loose.grf =\n\
+             \n[newgrf-static]\n\n",
+            config.to_cfg()
+        );
+
+        response.active_newgrf = ActiveNewGrf::OnlyId(vec![(0x0303474d, md5)]);
+        assert!(NewGrfConfig::from_server(&response, &index, &lookup, search_dir).is_err());
+
+        response.active_newgrf = ActiveNewGrf::OnlyId(vec![(0x0103474d, NewGRFHash([2; 16]))]);
+        assert!(NewGrfConfig::from_server(&response, &index, &lookup, search_dir).is_err());
+    }
+}
@@ -0,0 +1,279 @@
+use crate::newgrf::NewGRFHash;
+use md5::{Digest, Md5};
+use nom::{
+    self,
+    bytes::complete::{tag, take},
+    combinator::map,
+    number::complete::*,
+    sequence::tuple,
+    *,
+};
+use std::{
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+/// Start of a container version 2 file; version 1 files start with a sprite size
+const GRF_V2_MAGIC: [u8; 10] = *b"\x00\x00GRF\x82\x0d\x0a\x1a\x0a";
+
+/// Sprite type byte of pseudo sprites, which hold the NewGRF actions
+const PSEUDO_SPRITE: u8 = 0xFF;
+
+/// Length of the container version 2 header covered by the data section offset
+const GRF_V2_HEADER_LEN: usize = GRF_V2_MAGIC.len() + 4;
+
+/// MD5 of a `.grf` file as OpenTTD computes it
+///
+/// Version 1 containers are hashed whole. For version 2 only the header and
+/// the data section with the pseudo sprites count, so the sprite section
+/// holding the graphics does not change the identity of a NewGRF.
+pub fn grf_md5(data: &[u8]) -> NewGRFHash {
+    let len = match data.strip_prefix(&GRF_V2_MAGIC[..]) {
+        Some(rest) if rest.len() >= 4 => {
+            let offset = u32::from_le_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+            GRF_V2_HEADER_LEN.saturating_add(offset).min(data.len())
+        }
+        _ => data.len(),
+    };
+
+    NewGRFHash(Md5::digest(&data[..len]).into())
+}
+
+/// Container version 2 header: magic, offset of the sprite data section and compression
+fn v2_header(input: &[u8]) -> IResult<&[u8], ()> {
+    map(tuple((tag(&GRF_V2_MAGIC[..]), le_u32, le_u8)), |_| ()).parse(input)
+}
+
+/// Size and type of the next sprite; the size excludes the type byte
+fn sprite_header(input: &[u8], v2: bool) -> IResult<&[u8], (u32, u8)> {
+    if v2 {
+        tuple((le_u32, le_u8)).parse(input)
+    } else {
+        tuple((map(le_u16, u32::from), le_u8)).parse(input)
+    }
+}
+
+/// Pseudo sprites from the start of a `.grf` file
+///
+/// Stops at the first real sprite of a version 1 container, as their length
+/// is only known after decoding them; the action 8 sprite precedes them.
+pub(crate) fn pseudo_sprites(data: &[u8]) -> impl Iterator<Item = &[u8]> {
+    let (mut input, v2) = match v2_header(data) {
+        Ok((input, _)) => (input, true),
+        Err(_) => (data, false),
+    };
+
+    std::iter::from_fn(move || loop {
+        let (rest, (size, info)) = sprite_header(input, v2).ok()?;
+        if size == 0 || (info != PSEUDO_SPRITE && !v2) {
+            return None;
+        }
+
+        let (rest, sprite) = take::<_, _, error::Error<_>>(size).parse(rest).ok()?;
+        input = rest;
+        if info == PSEUDO_SPRITE {
+            return Some(sprite);
+        }
+    })
+}
+
+fn action8_grf_id(input: &[u8]) -> IResult<&[u8], u32> {
+    map(tuple((tag(&[0x08][..]), le_u8, le_u32)), |(_, _, grfid)| {
+        grfid
+    })
+    .parse(input)
+}
+
+/// GRF ID from the action 8 sprite of a `.grf` file
+pub fn grf_id(data: &[u8]) -> Option<u32> {
+    pseudo_sprites(data).find_map(|sprite| action8_grf_id(sprite).ok().map(|(_, grfid)| grfid))
+}
+
+/// GRF ID and MD5 of a `.grf` file, as servers list their NewGRFs
+///
+/// `None` if the file has no action 8, which OpenTTD rejects as well.
+pub fn grf_identity(data: &[u8]) -> Option<(u32, NewGRFHash)> {
+    grf_id(data).map(|grfid| (grfid, grf_md5(data)))
+}
+
+/// NewGRF found on disk by [`scan_newgrfs`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct LocalNewGrf {
+    /// `.grf` file, or the `.tar` archive containing it
+    pub path: PathBuf,
+    /// Path of the `.grf` inside the archive
+    pub tar_entry: Option<PathBuf>,
+    pub grfid: u32,
+    pub md5: NewGRFHash,
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case(extension))
+}
+
+/// GRF ID and MD5 of every `.grf` in a tar archive, in archive order
+pub fn tar_newgrfs<R: Read>(reader: R) -> anyhow::Result<Vec<(PathBuf, (u32, NewGRFHash))>> {
+    let mut out = vec![];
+    for entry in tar::Archive::new(reader).entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        if !entry.header().entry_type().is_file() || !has_extension(&path, "grf") {
+            continue;
+        }
+
+        let mut data = vec![];
+        entry.read_to_end(&mut data)?;
+        if let Some(identity) = grf_identity(&data) {
+            out.push((path, identity));
+        }
+    }
+
+    Ok(out)
+}
+
+/// Find the NewGRFs in a directory tree, both as `.grf` files and inside `.tar` archives
+///
+/// Like OpenTTD, compressed archives are not looked into. Results are sorted by path.
+pub fn scan_newgrfs<P: AsRef<Path>>(dir: P) -> anyhow::Result<Vec<LocalNewGrf>> {
+    let mut out = vec![];
+    let mut dirs = vec![dir.as_ref().to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            // Symlinks to directories are not followed, they may form a cycle
+            if entry.file_type()?.is_dir() {
+                dirs.push(path);
+            } else if has_extension(&path, "grf") {
+                if let Some((grfid, md5)) = grf_identity(&fs::read(&path)?) {
+                    out.push(LocalNewGrf {
+                        path,
+                        tar_entry: None,
+                        grfid,
+                        md5,
+                    });
+                }
+            } else if has_extension(&path, "tar") {
+                for (tar_entry, (grfid, md5)) in tar_newgrfs(fs::File::open(&path)?)? {
+                    out.push(LocalNewGrf {
+                        path: path.clone(),
+                        tar_entry: Some(tar_entry),
+                        grfid,
+                        md5,
+                    });
+                }
+            }
+        }
+    }
+    out.sort_by(|a, b| (&a.path, &a.tar_entry).cmp(&(&b.path, &b.tar_entry)));
+
+    Ok(out)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    fn action8(grfid: u32) -> Vec<u8> {
+        let mut out = vec![0x08, 0x08];
+        out.extend_from_slice(&grfid.to_le_bytes());
+        out.extend_from_slice(b"Test\0\0");
+        out
+    }
+
+    /// Container version 2 NewGRF with an action 8 and `graphics` as sprite section
+    pub(crate) fn grf_v2(grfid: u32, graphics: &[u8]) -> Vec<u8> {
+        let mut data = vec![];
+        for sprite in [&1_u32.to_le_bytes()[..], &action8(grfid)] {
+            data.extend_from_slice(&(sprite.len() as u32).to_le_bytes());
+            data.push(PSEUDO_SPRITE);
+            data.extend_from_slice(sprite);
+        }
+        data.extend_from_slice(&[0, 0, 0, 0]);
+
+        let mut out = GRF_V2_MAGIC.to_vec();
+        out.extend_from_slice(&(data.len() as u32 + 1).to_le_bytes());
+        out.push(0);
+        out.extend_from_slice(&data);
+        out.extend_from_slice(graphics);
+        out
+    }
+
+    /// Container version 1 NewGRF with `sprites` as pseudo sprites before its action 8
+    pub(crate) fn grf_v1(grfid: u32, sprites: &[&[u8]]) -> Vec<u8> {
+        let mut out = vec![];
+        let (count, action8) = (1_u32.to_le_bytes(), action8(grfid));
+        let sprites = [&[&count[..]], sprites, &[&action8]].concat();
+        for sprite in sprites {
+            out.extend_from_slice(&(sprite.len() as u16).to_le_bytes());
+            out.push(PSEUDO_SPRITE);
+            out.extend_from_slice(sprite);
+        }
+        // Real sprite, then the end marker and checksum
+        out.extend_from_slice(&[0x09, 0x00, 0x01, 1, 1, 0, 0, 0, 0, 0, 0, 0]);
+        out.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+        out
+    }
+
+    #[test]
+    fn test_grf_identity() {
+        let v1 = grf_v1(0x0103474d, &[]);
+        assert_eq!(
+            Some((0x0103474d, NewGRFHash(Md5::digest(&v1).into()))),
+            grf_identity(&v1)
+        );
+
+        let v2 = grf_v2(0x0203474d, b"graphics");
+        let (grfid, md5) = grf_identity(&v2).unwrap();
+        assert_eq!(0x0203474d, grfid);
+        assert_eq!(md5, grf_md5(&grf_v2(0x0203474d, b"other graphics")));
+        assert_ne!(md5, grf_md5(&grf_v2(0x0303474d, b"graphics")));
+
+        assert_eq!(None, grf_identity(b"not a grf"));
+    }
+
+    #[test]
+    fn test_scan_newgrfs() {
+        let dir = std::env::temp_dir().join(format!("openttd-newgrf-file-{}", std::process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+
+        let v1 = grf_v1(0x0103474d, &[]);
+        let v2 = grf_v2(0x0203474d, b"");
+        fs::write(dir.join("sub").join("a.GRF"), &v1).unwrap();
+        fs::write(dir.join("readme.txt"), b"hello").unwrap();
+
+        let mut builder = tar::Builder::new(vec![]);
+        for (name, data) in [("set/readme.txt", &b"hello"[..]), ("set/b.grf", &v2)] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_cksum();
+            builder.append_data(&mut header, name, data).unwrap();
+        }
+        fs::write(dir.join("b.tar"), builder.into_inner().unwrap()).unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&dir, dir.join("sub").join("loop")).unwrap();
+
+        let result = scan_newgrfs(&dir).unwrap();
+        assert_eq!(
+            vec![
+                LocalNewGrf {
+                    path: dir.join("b.tar"),
+                    tar_entry: Some("set/b.grf".into()),
+                    grfid: 0x0203474d,
+                    md5: grf_md5(&v2),
+                },
+                LocalNewGrf {
+                    path: dir.join("sub").join("a.GRF"),
+                    tar_entry: None,
+                    grfid: 0x0103474d,
+                    md5: grf_md5(&v1),
+                },
+            ],
+            result
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,489 @@
+use crate::{newgrf::NewGRFHash, newgrf_file::pseudo_sprites, util::*};
+use nom::{
+    self,
+    bytes::complete::{tag, take},
+    combinator::{map, opt},
+    number::complete::*,
+    sequence::tuple,
+    *,
+};
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+use std::{
+    collections::BTreeMap,
+    ffi::{CStr, CString},
+};
+
+/// Language ID of the text used when no translation matches
+pub const GRF_DEFAULT_LANGUAGE: u8 = 0x7F;
+
+/// Texts of an action 14 entry, keyed by language ID
+pub type GrfTranslations = BTreeMap<u8, CString>;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, IntoPrimitive, TryFromPrimitive)]
+#[repr(u8)]
+pub enum GrfParameterType {
+    #[default]
+    Int,
+    Bool,
+}
+
+/// User settable NewGRF parameter as described by action 14
+#[derive(Clone, Debug, PartialEq)]
+pub struct GrfParameterInfo {
+    pub name: GrfTranslations,
+    pub description: GrfTranslations,
+    pub param_type: GrfParameterType,
+    pub min_value: u32,
+    pub max_value: u32,
+    pub default_value: u32,
+    /// Parameter holding the value, with its bit range
+    pub param_nr: u8,
+    pub first_bit: u8,
+    pub num_bits: u8,
+    /// Names of individual values
+    pub value_names: BTreeMap<u32, GrfTranslations>,
+}
+
+impl GrfParameterInfo {
+    fn new(param_nr: u8) -> Self {
+        Self {
+            name: GrfTranslations::new(),
+            description: GrfTranslations::new(),
+            param_type: GrfParameterType::Int,
+            min_value: 0,
+            max_value: u32::MAX,
+            default_value: 0,
+            param_nr,
+            first_bit: 0,
+            num_bits: 32,
+            value_names: BTreeMap::new(),
+        }
+    }
+}
+
+/// Action 14 static NewGRF information
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GrfStaticInfo {
+    pub name: GrfTranslations,
+    pub description: GrfTranslations,
+    pub url: GrfTranslations,
+    pub version: Option<u32>,
+    /// Oldest version savegames may use when upgrading to this one
+    pub min_compatible_version: Option<u32>,
+    pub num_params: Option<u8>,
+    pub palette: Option<u8>,
+    /// Parameters by their number in the NewGRF settings
+    pub parameters: BTreeMap<u32, GrfParameterInfo>,
+}
+
+/// Identity and description of a `.grf` file, from its actions 8 and 14
+#[derive(Clone, Debug, PartialEq)]
+pub struct GrfHeader {
+    /// NewGRF format version from action 8
+    pub grf_version: u8,
+    pub grfid: u32,
+    pub name: CString,
+    pub description: CString,
+    pub static_info: GrfStaticInfo,
+}
+
+impl GrfHeader {
+    /// Parse the pseudo sprites up to action 8, like OpenTTD when scanning NewGRFs
+    pub fn from_grf(data: &[u8]) -> Option<Self> {
+        let mut static_info = GrfStaticInfo::default();
+        for sprite in pseudo_sprites(data) {
+            match sprite.first() {
+                Some(0x14) => {
+                    if let Ok((_, chunks)) = chunk_list(&sprite[1..]) {
+                        static_info.apply(&chunks);
+                    }
+                }
+                Some(0x08) => {
+                    let (_, (grf_version, grfid, name, description)) = action8(sprite).ok()?;
+                    // Like OpenTTD, the action 8 texts replace the default translations
+                    static_info.name.insert(GRF_DEFAULT_LANGUAGE, name.clone());
+                    if let Some(description) = &description {
+                        static_info
+                            .description
+                            .insert(GRF_DEFAULT_LANGUAGE, description.clone());
+                    }
+                    return Some(Self {
+                        grf_version,
+                        grfid,
+                        name,
+                        description: description.unwrap_or_default(),
+                        static_info,
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        None
+    }
+
+    /// Name for `language`, falling back to the default translation and the action 8 name
+    pub fn name(&self, language: u8) -> &CStr {
+        translation(&self.static_info.name, language).unwrap_or(&self.name)
+    }
+
+    pub fn description(&self, language: u8) -> &CStr {
+        translation(&self.static_info.description, language).unwrap_or(&self.description)
+    }
+
+    /// Entry of [`ActiveNewGrf::Full`](crate::ActiveNewGrf::Full) for this NewGRF
+    pub fn active_entry(&self, md5: NewGRFHash) -> (u32, (NewGRFHash, CString)) {
+        (
+            self.grfid,
+            (md5, self.name(GRF_DEFAULT_LANGUAGE).to_owned()),
+        )
+    }
+}
+
+fn translation(texts: &GrfTranslations, language: u8) -> Option<&CStr> {
+    texts
+        .get(&language)
+        .or_else(|| texts.get(&GRF_DEFAULT_LANGUAGE))
+        .map(CString::as_c_str)
+}
+
+/// The description is optional, OpenTTD only reads it when the sprite continues
+fn action8(input: &[u8]) -> IResult<&[u8], (u8, u32, CString, Option<CString>)> {
+    map(
+        tuple((
+            tag(&[0x08][..]),
+            le_u8,
+            le_u32,
+            read_cstring,
+            opt(read_cstring),
+        )),
+        |(_, grf_version, grfid, name, description)| (grf_version, grfid, name, description),
+    )
+    .parse(input)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Chunk<'a> {
+    Container([u8; 4], Vec<Chunk<'a>>),
+    Text([u8; 4], u8, CString),
+    Binary([u8; 4], &'a [u8]),
+}
+
+fn chunk_id(input: &[u8]) -> IResult<&[u8], [u8; 4]> {
+    map(take(4_usize), |v: &[u8]| [v[0], v[1], v[2], v[3]]).parse(input)
+}
+
+/// Action 14 chunks up to the terminating zero byte
+fn chunk_list(mut input: &[u8]) -> IResult<&[u8], Vec<Chunk<'_>>> {
+    let mut out = vec![];
+    loop {
+        let (rest, chunk_type) = le_u8(input)?;
+        let (rest, chunk) = match chunk_type {
+            0 => return Ok((rest, out)),
+            b'C' => map(tuple((chunk_id, chunk_list)), |(id, chunks)| {
+                Chunk::Container(id, chunks)
+            })
+            .parse(rest)?,
+            b'T' => map(
+                tuple((chunk_id, le_u8, read_cstring)),
+                |(id, lang, text)| Chunk::Text(id, lang, text),
+            )
+            .parse(rest)?,
+            b'B' => {
+                let (rest, (id, len)) = tuple((chunk_id, le_u16)).parse(rest)?;
+                map(take(len), move |data| Chunk::Binary(id, data)).parse(rest)?
+            }
+            _ => {
+                return Err(nom::Err::Error(error::Error::new(
+                    input,
+                    error::ErrorKind::Switch,
+                )))
+            }
+        };
+        out.push(chunk);
+        input = rest;
+    }
+}
+
+fn dword(data: &[u8]) -> Option<u32> {
+    Some(u32::from_le_bytes(data.try_into().ok()?))
+}
+
+fn byte(data: &[u8]) -> Option<u8> {
+    match data {
+        [v] => Some(*v),
+        _ => None,
+    }
+}
+
+impl GrfStaticInfo {
+    fn apply(&mut self, chunks: &[Chunk]) {
+        for chunk in chunks {
+            if let Chunk::Container(id, chunks) = chunk {
+                if id == b"INFO" {
+                    self.apply_info(chunks);
+                }
+            }
+        }
+    }
+
+    fn apply_info(&mut self, chunks: &[Chunk]) {
+        for chunk in chunks {
+            match chunk {
+                Chunk::Text(id, lang, text) => {
+                    let texts = match id {
+                        b"NAME" => &mut self.name,
+                        b"DESC" => &mut self.description,
+                        b"URL_" => &mut self.url,
+                        _ => continue,
+                    };
+                    texts.insert(*lang, text.clone());
+                }
+                Chunk::Binary(id, data) => match id {
+                    b"VRSN" => self.version = dword(data),
+                    b"MINV" => self.min_compatible_version = dword(data),
+                    b"NPAR" => self.num_params = byte(data),
+                    b"PALS" => self.palette = byte(data),
+                    _ => {}
+                },
+                Chunk::Container(id, chunks) => {
+                    if id != b"PARA" {
+                        continue;
+                    }
+                    for chunk in chunks {
+                        if let Chunk::Container(id, chunks) = chunk {
+                            let nr = u32::from_le_bytes(*id);
+                            let info = self
+                                .parameters
+                                .entry(nr)
+                                .or_insert_with(|| GrfParameterInfo::new(nr as u8));
+                            info.apply(chunks);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl GrfParameterInfo {
+    fn apply(&mut self, chunks: &[Chunk]) {
+        for chunk in chunks {
+            match chunk {
+                Chunk::Text(id, lang, text) => {
+                    let texts = match id {
+                        b"NAME" => &mut self.name,
+                        b"DESC" => &mut self.description,
+                        _ => continue,
+                    };
+                    texts.insert(*lang, text.clone());
+                }
+                Chunk::Binary(id, data) => match id {
+                    b"TYPE" => {
+                        if let Some(param_type) =
+                            byte(data).and_then(|v| GrfParameterType::try_from(v).ok())
+                        {
+                            self.param_type = param_type;
+                            if param_type == GrfParameterType::Bool {
+                                self.min_value = 0;
+                                self.max_value = 1;
+                            }
+                        }
+                    }
+                    b"LIMI" if data.len() == 8 => {
+                        let (min, max) = (dword(&data[..4]).unwrap(), dword(&data[4..]).unwrap());
+                        // Like OpenTTD, an inverted range is ignored
+                        if min <= max {
+                            self.min_value = min;
+                            self.max_value = max;
+                        }
+                    }
+                    b"MASK" if (1..=3).contains(&data.len()) => {
+                        self.param_nr = data[0];
+                        if let Some(&first_bit) = data.get(1) {
+                            self.first_bit = first_bit.min(31);
+                            self.num_bits = 32 - self.first_bit;
+                        }
+                        if let Some(&num_bits) = data.get(2) {
+                            self.num_bits = num_bits.min(32 - self.first_bit);
+                        }
+                    }
+                    b"DFLT" => {
+                        if let Some(value) = dword(data) {
+                            self.default_value = value;
+                        }
+                    }
+                    _ => {}
+                },
+                Chunk::Container(id, chunks) => {
+                    if id != b"VALU" {
+                        continue;
+                    }
+                    for chunk in chunks {
+                        if let Chunk::Text(id, lang, text) = chunk {
+                            self.value_names
+                                .entry(u32::from_le_bytes(*id))
+                                .or_default()
+                                .insert(*lang, text.clone());
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::newgrf_file::tests::grf_v1;
+    use maplit::btreemap;
+
+    fn text(id: &[u8; 4], lang: u8, text: &str) -> Vec<u8> {
+        let mut out = vec![b'T'];
+        out.extend_from_slice(id);
+        out.push(lang);
+        out.extend_from_slice(text.as_bytes());
+        out.push(0);
+        out
+    }
+
+    fn binary(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut out = vec![b'B'];
+        out.extend_from_slice(id);
+        out.extend_from_slice(&(data.len() as u16).to_le_bytes());
+        out.extend_from_slice(data);
+        out
+    }
+
+    fn container(id: &[u8; 4], chunks: &[Vec<u8>]) -> Vec<u8> {
+        let mut out = vec![b'C'];
+        out.extend_from_slice(id);
+        out.extend(chunks.concat());
+        out.push(0);
+        out
+    }
+
+    fn cstring(s: &str) -> CString {
+        CString::new(s).unwrap()
+    }
+
+    #[test]
+    fn test_grf_header() {
+        let parameter = container(
+            &0_u32.to_le_bytes(),
+            &[
+                text(b"NAME", 0x7F, "Costs"),
+                binary(
+                    b"LIMI",
+                    &[&0_u32.to_le_bytes()[..], &2_u32.to_le_bytes()].concat(),
+                ),
+                binary(b"MASK", &[1, 8, 2]),
+                binary(b"DFLT", &1_u32.to_le_bytes()),
+                container(
+                    b"VALU",
+                    &[
+                        text(&0_u32.to_le_bytes(), 0x7F, "Low"),
+                        text(&1_u32.to_le_bytes(), 0x7F, "Normal"),
+                    ],
+                ),
+            ],
+        );
+        let inverted = container(
+            &1_u32.to_le_bytes(),
+            &[
+                text(b"NAME", 0x7F, "Inverted"),
+                binary(
+                    b"LIMI",
+                    &[&2_u32.to_le_bytes()[..], &0_u32.to_le_bytes()].concat(),
+                ),
+            ],
+        );
+        let mut action14 = vec![0x14];
+        action14.extend(container(
+            b"INFO",
+            &[
+                text(b"NAME", 0x7F, "Test Set"),
+                text(b"NAME", 0x02, "Testsatz"),
+                text(b"DESC", 0x7F, "For testing"),
+                binary(b"VRSN", &7_u32.to_le_bytes()),
+                binary(b"MINV", &5_u32.to_le_bytes()),
+                binary(b"NPAR", &[2]),
+                container(b"PARA", &[parameter, inverted]),
+            ],
+        ));
+        action14.push(0);
+        let grf = grf_v1(0x0103474d, &[&action14]);
+
+        let header = GrfHeader::from_grf(&grf).unwrap();
+        let expectation = GrfHeader {
+            grf_version: 8,
+            grfid: 0x0103474d,
+            name: cstring("Test"),
+            description: cstring(""),
+            static_info: GrfStaticInfo {
+                name: btreemap! {0x02 => cstring("Testsatz"), 0x7F => cstring("Test")},
+                description: btreemap! {0x7F => cstring("")},
+                url: BTreeMap::new(),
+                version: Some(7),
+                min_compatible_version: Some(5),
+                num_params: Some(2),
+                palette: None,
+                parameters: btreemap! {
+                    0 => GrfParameterInfo {
+                        name: btreemap! {0x7F => cstring("Costs")},
+                        description: BTreeMap::new(),
+                        param_type: GrfParameterType::Int,
+                        min_value: 0,
+                        max_value: 2,
+                        default_value: 1,
+                        param_nr: 1,
+                        first_bit: 8,
+                        num_bits: 2,
+                        value_names: btreemap! {
+                            0 => btreemap! {0x7F => cstring("Low")},
+                            1 => btreemap! {0x7F => cstring("Normal")},
+                        },
+                    },
+                    1 => GrfParameterInfo {
+                        name: btreemap! {0x7F => cstring("Inverted")},
+                        ..GrfParameterInfo::new(1)
+                    },
+                },
+            },
+        };
+        assert_eq!(expectation, header);
+
+        assert_eq!(cstring("Testsatz").as_c_str(), header.name(0x02));
+        assert_eq!(cstring("").as_c_str(), header.description(0x02));
+        assert_eq!(cstring("Test").as_c_str(), header.name(0x7F));
+        let md5 = NewGRFHash([1; 16]);
+        assert_eq!(
+            (0x0103474d, (md5, cstring("Test"))),
+            header.active_entry(md5)
+        );
+    }
+
+    #[test]
+    fn test_action8_without_description() {
+        let mut action14 = vec![0x14];
+        action14.extend(container(
+            b"INFO",
+            &[
+                text(b"NAME", 0x7F, "Test Set"),
+                text(b"DESC", 0x7F, "For testing"),
+            ],
+        ));
+        action14.push(0);
+        // Scanning stops at this action 8, before the one `grf_v1` appends
+        let action8 = [&[0x08, 0x08][..], &0x0203474d_u32.to_le_bytes(), b"Short\0"].concat();
+        let grf = grf_v1(0x0103474d, &[&action14, &action8]);
+
+        let header = GrfHeader::from_grf(&grf).unwrap();
+        assert_eq!(0x0203474d, header.grfid);
+        assert_eq!(cstring("Short"), header.name);
+        assert_eq!(cstring(""), header.description);
+        assert_eq!(cstring("Short").as_c_str(), header.name(0x7F));
+        assert_eq!(cstring("For testing").as_c_str(), header.description(0x7F));
+    }
+}
@@ -0,0 +1,148 @@
+use crate::{util::*, GamePacket};
+use byteorder::{LittleEndian, WriteBytesExt};
+use nom::{self, combinator::map, number::complete::*, sequence::tuple, *};
+use std::ffi::CString;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClientRconPacket {
+    pub password: CString,
+    pub command: CString,
+}
+
+impl ByteWriter for ClientRconPacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.extend_from_slice(self.password.to_bytes_with_nul());
+        buf.extend_from_slice(self.command.to_bytes_with_nul());
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for ClientRconPacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(
+            tuple((read_cstring, read_cstring)),
+            |(password, command)| Self { password, command },
+        )
+        .parse(input)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerRconPacket {
+    /// `TextColour` the line is printed with in the server console
+    pub colour: u16,
+    pub output: CString,
+}
+
+impl ByteWriter for ServerRconPacket {
+    fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
+        buf.write_u16::<LittleEndian>(self.colour)?;
+        buf.extend_from_slice(self.output.to_bytes_with_nul());
+
+        Ok(())
+    }
+}
+
+impl PacketPayload for ServerRconPacket {
+    fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
+        map(tuple((le_u16, read_cstring)), |(colour, output)| Self {
+            colour,
+            output,
+        })
+        .parse(input)
+    }
+}
+
+/// Collects the output lines the server sends back for a single RCON command.
+///
+/// The game protocol has no end-of-output marker. The server writes every line of a command
+/// before its next `SERVER_FRAME`, so the first frame after the command closes the collection,
+/// also for commands that print nothing. Create the collector right after sending the command.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RconOutput {
+    pub lines: Vec<ServerRconPacket>,
+}
+
+impl RconOutput {
+    /// Feed a received packet. Returns `true` once the output of the command is complete.
+    pub fn push(&mut self, packet: &GamePacket) -> bool {
+        match packet {
+            GamePacket::ServerRcon(line) => {
+                self.lines.push(line.clone());
+                false
+            }
+            GamePacket::ServerFrame(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Output lines as text, without colour information
+    pub fn text(&self) -> Vec<String> {
+        self.lines
+            .iter()
+            .map(|line| line.output.to_string_lossy().into_owned())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ServerFramePacket;
+    use hex_literal::hex;
+
+    #[test]
+    fn test_collect_rcon_output() {
+        let packets = [
+            GamePacket::ServerRcon(ServerRconPacket {
+                colour: 1,
+                output: CString::new("Current version: 13.4").unwrap(),
+            }),
+            GamePacket::ServerRcon(ServerRconPacket {
+                colour: 1,
+                output: CString::new("Dedicated server").unwrap(),
+            }),
+            GamePacket::ServerFrame(ServerFramePacket {
+                frame_counter_server: 100,
+                frame_counter_max: 110,
+                token: None,
+            }),
+        ];
+
+        let mut output = RconOutput::default();
+        let done = packets
+            .iter()
+            .map(|packet| output.push(packet))
+            .collect::<Vec<_>>();
+
+        assert_eq!(done, [false, false, true]);
+        assert_eq!(output.text(), ["Current version: 13.4", "Dedicated server"]);
+    }
+
+    #[test]
+    fn test_collect_empty_rcon_output() {
+        let mut output = RconOutput::default();
+        let frame = GamePacket::ServerFrame(ServerFramePacket {
+            frame_counter_server: 100,
+            frame_counter_max: 110,
+            token: None,
+        });
+
+        assert!(output.push(&frame));
+        assert!(output.text().is_empty());
+    }
+
+    #[test]
+    fn test_write_client_rcon() {
+        let packet = GamePacket::ClientRcon(ClientRconPacket {
+            password: CString::new("pw").unwrap(),
+            command: CString::new("info").unwrap(),
+        });
+
+        let bytes = packet.to_bytes().unwrap();
+
+        assert_eq!(bytes, hex!("0b00 22 707700 696e666f00"));
+        assert_eq!(GamePacket::from_bytes(&bytes).unwrap().1, packet);
+    }
+}
@@ -1,6 +1,5 @@
-use crate::{newgrf::ActiveNewGrfDiscriminants, util::*, ActiveNewGrf};
+use crate::{game_date::*, newgrf::ActiveNewGrfDiscriminants, util::*, ActiveNewGrf};
 use byteorder::{LittleEndian, WriteBytesExt};
-use chrono::prelude::*;
 use nom::{self, combinator::map, number::complete::*, sequence::Tuple, *};
 use num_enum::TryFromPrimitive;
 use std::ffi::CString;
@@ -8,12 +7,17 @@ use std::ffi::CString;
 #[derive(Clone, Debug, PartialEq)]
 pub enum ProtocolVer {
     V6,
+    /// OpenTTD 14: adds the ticks the game has been played
+    V7 {
+        ticks_playing: u64,
+    },
 }
 
 impl<'a> From<&'a ProtocolVer> for u8 {
     fn from(v: &'a ProtocolVer) -> u8 {
         match *v {
             ProtocolVer::V6 => 6,
+            ProtocolVer::V7 { .. } => 7,
         }
     }
 }
@@ -23,6 +27,9 @@ impl ByteWriter for ProtocolVer {
         buf.push(self.into());
         match *self {
             ProtocolVer::V6 => {}
+            ProtocolVer::V7 { ticks_playing } => {
+                buf.write_u64::<LittleEndian>(ticks_playing)?;
+            }
         }
         Ok(())
     }
@@ -32,6 +39,7 @@ fn protocol_ver(input: &[u8]) -> IResult<&[u8], ProtocolVer> {
     let (input, protocol_num) = le_u8(input)?;
     match protocol_num {
         6 => Ok((input, ProtocolVer::V6)),
+        7 => map(le_u64, |ticks_playing| ProtocolVer::V7 { ticks_playing }).parse(input),
         _ => Err(nom::Err::Failure(nom::error::Error {
             input,
             code: nom::error::ErrorKind::OneOf,
@@ -39,17 +47,15 @@ fn protocol_ver(input: &[u8]) -> IResult<&[u8], ProtocolVer> {
     }
 }
 
-fn timestamp(input: &[u8]) -> IResult<&[u8], DateTime<Utc>> {
-    map(le_u32, datetime_from_ts).parse(input)
-}
-
 #[derive(Clone, Debug, PartialEq)]
 pub struct ServerResponse {
+    /// Ticks the game has been played; only sent by OpenTTD 14 and newer
+    pub ticks_playing: Option<u64>,
     pub gamescript_version: u32,
     pub gamescript_name: CString,
     pub active_newgrf: ActiveNewGrf,
-    pub game_date: DateTime<Utc>,
-    pub start_date: DateTime<Utc>,
+    pub game_date: GameDate,
+    pub start_date: GameDate,
     pub max_companies: u8,
     pub current_companies: u8,
     pub max_spectators: u8,
@@ -69,7 +75,11 @@ pub struct ServerResponse {
 
 impl ByteWriter for ServerResponse {
     fn write_pkt(&self, buf: &mut Vec<u8>) -> std::io::Result<()> {
-        ProtocolVer::V6.write_pkt(buf)?;
+        match self.ticks_playing {
+            Some(ticks_playing) => ProtocolVer::V7 { ticks_playing },
+            None => ProtocolVer::V6,
+        }
+        .write_pkt(buf)?;
 
         buf.push(ActiveNewGrfDiscriminants::from(&self.active_newgrf).into());
 
@@ -84,7 +94,7 @@ impl ByteWriter for ServerResponse {
                         "NewGRF maximum number is 255",
                     )
                 })?);
-                for (&id, hash) in ids {
+                for &(id, hash) in ids {
                     buf.write_u32::<LittleEndian>(id)?;
                     buf.extend_from_slice(&hash.0);
                 }
@@ -96,8 +106,8 @@ impl ByteWriter for ServerResponse {
                         "NewGRF maximum number is 255",
                     )
                 })?);
-                for (&id, (hash, name)) in ids {
-                    buf.write_u32::<LittleEndian>(id)?;
+                for (id, (hash, name)) in ids {
+                    buf.write_u32::<LittleEndian>(*id)?;
                     buf.extend_from_slice(&hash.0);
                     buf.append(&mut name.clone().into_bytes_with_nul());
                 }
@@ -115,8 +125,8 @@ impl ByteWriter for ServerResponse {
             }
         }
 
-        buf.write_u32::<LittleEndian>(self.game_date.timestamp() as u32)?;
-        buf.write_u32::<LittleEndian>(self.start_date.timestamp() as u32)?;
+        buf.write_u32::<LittleEndian>(self.game_date.0)?;
+        buf.write_u32::<LittleEndian>(self.start_date.0)?;
 
         buf.push(self.max_companies);
         buf.push(self.current_companies);
@@ -142,8 +152,12 @@ impl ByteWriter for ServerResponse {
 
 impl PacketPayload for ServerResponse {
     fn from_bytes(input: &[u8]) -> IResult<&[u8], Self> {
-        let (input, (_, kind, gamescript_version, gamescript_name)) =
+        let (input, (version, kind, gamescript_version, gamescript_name)) =
             (protocol_ver, le_u8, le_u32, read_cstring).parse(input)?;
+        let ticks_playing = match version {
+            ProtocolVer::V6 => None,
+            ProtocolVer::V7 { ticks_playing } => Some(ticks_playing),
+        };
 
         let (input, active_newgrf) = ActiveNewGrf::from_bytes(
             input,
@@ -177,8 +191,8 @@ impl PacketPayload for ServerResponse {
                 dedicated,
             ),
         ) = (
-            timestamp,
-            timestamp,
+            game_date,
+            game_date,
             le_u8,
             le_u8,
             le_u8,
@@ -199,6 +213,7 @@ impl PacketPayload for ServerResponse {
         Ok((
             input,
             Self {
+                ticks_playing,
                 gamescript_version,
                 gamescript_name,
                 active_newgrf,
@@ -225,11 +240,10 @@ impl PacketPayload for ServerResponse {
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
     use crate::NewGRFHash;
     use hex_literal::hex;
-    use maplit::btreemap;
 
     pub(crate) fn fixtures() -> (Vec<u8>, ServerResponse) {
         let b = hex!(
@@ -238,23 +252,27 @@ mod tests {
         .to_vec();
 
         let srv = ServerResponse {
+            ticks_playing: None,
             gamescript_version: u32::MAX,
             gamescript_name: CString::default(),
 
-            active_newgrf: ActiveNewGrf::OnlyId(btreemap! {
-                0x00074e44 => NewGRFHash(hex!("48b3f9e4fd0df2a72b5f44d3c8a2f4a0")),
-                0x0503474d => NewGRFHash(hex!("2e96b9ab2bea686bff94961ad433a701")),
-                0x22333232 => NewGRFHash(hex!("316180da1ba6444a06cd17f8fa79d60a")),
-            }),
-
-            game_date: DateTime::from_utc(
-                NaiveDateTime::from_timestamp_opt(715875, 0).unwrap(),
-                Utc,
-            ),
-            start_date: DateTime::from_utc(
-                NaiveDateTime::from_timestamp_opt(715875, 0).unwrap(),
-                Utc,
-            ),
+            active_newgrf: ActiveNewGrf::OnlyId(vec![
+                (
+                    0x00074e44,
+                    NewGRFHash(hex!("48b3f9e4fd0df2a72b5f44d3c8a2f4a0")),
+                ),
+                (
+                    0x0503474d,
+                    NewGRFHash(hex!("2e96b9ab2bea686bff94961ad433a701")),
+                ),
+                (
+                    0x22333232,
+                    NewGRFHash(hex!("316180da1ba6444a06cd17f8fa79d60a")),
+                ),
+            ]),
+
+            game_date: GameDate(715875),
+            start_date: GameDate(715875),
 
             max_companies: 15,
             current_companies: 0,
@@ -295,4 +313,48 @@ mod tests {
 
         assert_eq!(expectation, result);
     }
+
+    #[test]
+    fn test_server_response_v7() {
+        let (v6, mut expectation) = fixtures();
+        expectation.ticks_playing = Some(0x1234);
+        let mut input = hex!("07 3412000000000000").to_vec();
+        input.extend_from_slice(&v6[1..]);
+
+        let (rest, result) = ServerResponse::from_bytes(&input).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(expectation, result);
+
+        let mut written = Vec::new();
+        result.write_pkt(&mut written).unwrap();
+        assert_eq!(input, written);
+    }
+
+    #[test]
+    fn test_active_newgrf_order() {
+        let (_, mut srv) = fixtures();
+        srv.active_newgrf = ActiveNewGrf::Full(vec![
+            (
+                0x22333232,
+                (NewGRFHash([2; 16]), CString::new("b").unwrap()),
+            ),
+            (
+                0x00074e44,
+                (NewGRFHash([1; 16]), CString::new("a").unwrap()),
+            ),
+            (
+                0x22333232,
+                (NewGRFHash([2; 16]), CString::new("b").unwrap()),
+            ),
+        ]);
+
+        let mut bytes = Vec::new();
+        srv.write_pkt(&mut bytes).unwrap();
+        let (_, result) = ServerResponse::from_bytes(&bytes).unwrap();
+        let mut rewritten = Vec::new();
+        result.write_pkt(&mut rewritten).unwrap();
+
+        assert_eq!(srv, result);
+        assert_eq!(bytes, rewritten);
+    }
 }
@@ -0,0 +1,23 @@
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// Read one size-prefixed packet from a TCP stream, including its size field
+pub async fn read_frame<R>(reader: &mut R) -> std::io::Result<Vec<u8>>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut frame = vec![0; 2];
+    reader.read_exact(&mut frame).await?;
+
+    let size = usize::from(u16::from_le_bytes([frame[0], frame[1]]));
+    if size < 3 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("invalid packet size {}", size),
+        ));
+    }
+
+    frame.resize(size, 0);
+    reader.read_exact(&mut frame[2..]).await?;
+
+    Ok(frame)
+}
@@ -1,7 +1,7 @@
-use chrono::prelude::*;
 use nom::{
     bytes::complete::{take, take_till},
-    combinator::map_res,
+    combinator::{map, map_res},
+    number::complete::le_u8,
     IResult, Parser,
 };
 use std::ffi::CString;
@@ -12,11 +12,9 @@ pub fn read_cstring(input: &[u8]) -> IResult<&[u8], CString> {
     Ok((input, s))
 }
 
-pub fn datetime_from_ts<T: Into<i64>>(ts: T) -> DateTime<Utc> {
-    DateTime::from_utc(
-        NaiveDateTime::from_timestamp_opt(ts.into(), 0).unwrap(),
-        Utc,
-    )
+/// Any non-zero byte is true, as OpenTTD's `Recv_bool`
+pub fn boolean(input: &[u8]) -> IResult<&[u8], bool> {
+    map(le_u8, |v| v > 0).parse(input)
 }
 
 pub trait ByteWriter {